@@ -63,6 +63,10 @@ fn fragment(
     var shaded_color: vec4<f32> = material_color * input.hash_color;
     // Sampling texture
     var texture_color = textureSample(material_color_texture, material_color_sampler, input.uv_coord);
+    // Dropping the transparent parts of the texture, such as the background of plants
+    if texture_color.a < 0.5 {
+        discard;
+    }
     // Computing a factor between 0 and 1 to create a fog effect based on the distance to the camera
     var fog_dist = 1 - exp(-0.0000007/(input.clip_position.z * input.clip_position.z));
 
@@ -0,0 +1,175 @@
+//! The ways the world is seen: through the eyes of the player, from behind or in front of them, or
+//! from a camera orbiting around them for screenshots. The cameras away from the player are pulled
+//! in front of the blocks standing between them and the player.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::{input::mouse::AccumulatedMouseScroll, prelude::*};
+
+use super::input::ActionState;
+use super::player::{Player, PlayerBody, WorldModelCamera};
+use super::world::{FaceType, VxWorld};
+
+const SWITCH_KEY: KeyCode = KeyCode::F5;
+const ORBIT_KEY: KeyCode = KeyCode::F6;
+/// Distance between the player and the third person cameras
+const THIRD_PERSON_DISTANCE: f32 = 4.0;
+const ORBIT_DISTANCE_RANGE: (f32, f32) = (2.0, 32.0);
+/// Space kept between a pulled in camera and the block in the way, so the near plane of the
+/// camera does not cut through it
+const CAMERA_MARGIN: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    #[default]
+    FirstPerson,
+    /// Behind the player, looking where they look
+    ThirdPersonBack,
+    /// In front of the player, looking at them
+    ThirdPersonFront,
+    /// Turning around the player with the look input, moved closer or further with the wheel
+    Orbit,
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct CameraRig {
+    pub mode: CameraMode,
+    /// The angles of the orbit camera around the player, in radians
+    pub orbit_yaw: f32,
+    pub orbit_pitch: f32,
+    pub orbit_distance: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            orbit_yaw: 0.0,
+            orbit_pitch: -0.4,
+            orbit_distance: 8.0,
+        }
+    }
+}
+
+/// Run condition of the systems turning the player, which the orbit camera takes the input of
+pub fn not_orbiting(rig: Res<CameraRig>) -> bool {
+    rig.mode != CameraMode::Orbit
+}
+
+/// F5 goes through the first and third person cameras, F6 enters or leaves the orbit camera
+pub fn switch_camera_mode(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player: Query<&Transform, With<Player>>,
+    mut rig: ResMut<CameraRig>,
+) {
+    if keyboard_input.just_pressed(SWITCH_KEY) {
+        rig.mode = match rig.mode {
+            CameraMode::FirstPerson => CameraMode::ThirdPersonBack,
+            CameraMode::ThirdPersonBack => CameraMode::ThirdPersonFront,
+            CameraMode::ThirdPersonFront | CameraMode::Orbit => CameraMode::FirstPerson,
+        };
+    }
+    if keyboard_input.just_pressed(ORBIT_KEY) {
+        if rig.mode == CameraMode::Orbit {
+            rig.mode = CameraMode::FirstPerson;
+        } else if let Ok(transform) = player.single() {
+            // Starts from behind the player
+            let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+            rig.orbit_yaw = yaw;
+            rig.mode = CameraMode::Orbit;
+        }
+    }
+}
+
+/// Turns the orbit camera with the look input, and moves it with the wheel
+pub fn control_orbit(
+    actions: Res<ActionState>,
+    accumulated_mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut rig: ResMut<CameraRig>,
+) {
+    if rig.mode != CameraMode::Orbit {
+        return;
+    }
+    rig.orbit_yaw += actions.look.x;
+    const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
+    rig.orbit_pitch = (rig.orbit_pitch + actions.look.y).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+    rig.orbit_distance = (rig.orbit_distance * 0.9_f32.powf(accumulated_mouse_scroll.delta.y))
+        .clamp(ORBIT_DISTANCE_RANGE.0, ORBIT_DISTANCE_RANGE.1);
+}
+
+// Distance along a ray to where it enters the face of the voxel it hit
+fn hit_distance(origin: Vec3, direction: Vec3, position: IVec3, face: FaceType) -> f32 {
+    let (x, y, z): (i8, i8, i8) = face.into();
+    let normal = Vec3::new(x as f32, y as f32, z as f32);
+    // The face lies half a block away from the center of the voxel
+    let plane = position.as_vec3() + 0.5 * normal;
+    let axis = normal.abs();
+    (plane - origin).dot(axis) / direction.dot(axis)
+}
+
+/// How far a camera can go from the player along a direction before a block gets in the way
+fn free_distance(my_world: &VxWorld, eye: Vec3, direction: Vec3, distance: f32) -> f32 {
+    match my_world.raycast(eye, direction, distance + CAMERA_MARGIN) {
+        Some((position, face)) => {
+            (hit_distance(eye, direction, position, face) - CAMERA_MARGIN).clamp(0.0, distance)
+        }
+        None => distance,
+    }
+}
+
+// The player, apart from its camera and body which are children of it
+type PlayerFilter = (With<Player>, Without<WorldModelCamera>, Without<PlayerBody>);
+
+/// Places the camera following its mode, and shows the body of the player when it is seen
+pub fn update_camera(
+    rig: Res<CameraRig>,
+    my_world: Option<Res<VxWorld>>,
+    player: Query<&Transform, PlayerFilter>,
+    mut camera: Query<&mut Transform, (With<WorldModelCamera>, Without<PlayerBody>)>,
+    mut body: Query<(&mut Transform, &mut Visibility), With<PlayerBody>>,
+) {
+    let (Ok(player), Ok(mut camera)) = (player.single(), camera.single_mut()) else {
+        return;
+    };
+    let eye = player.translation;
+    let (yaw, pitch, _) = player.rotation.to_euler(EulerRot::YXZ);
+
+    // The camera in world space, looking towards its -Z axis
+    let world_camera = match rig.mode {
+        CameraMode::FirstPerson => *player,
+        CameraMode::ThirdPersonBack | CameraMode::ThirdPersonFront | CameraMode::Orbit => {
+            let rotation = match rig.mode {
+                CameraMode::ThirdPersonBack => player.rotation,
+                CameraMode::ThirdPersonFront => {
+                    Quat::from_euler(EulerRot::YXZ, yaw + PI, -pitch, 0.0)
+                }
+                _ => Quat::from_euler(EulerRot::YXZ, rig.orbit_yaw, rig.orbit_pitch, 0.0),
+            };
+            let distance = match rig.mode {
+                CameraMode::Orbit => rig.orbit_distance,
+                _ => THIRD_PERSON_DISTANCE,
+            };
+            // The camera stands behind its own view direction, looking at the player
+            let backward = rotation * Vec3::Z;
+            let distance = match &my_world {
+                Some(my_world) => free_distance(my_world, eye, backward, distance),
+                None => distance,
+            };
+            Transform::from_translation(eye + backward * distance).with_rotation(rotation)
+        }
+    };
+    // The camera is a child of the player
+    *camera =
+        Transform::from_matrix(player.compute_matrix().inverse() * world_camera.compute_matrix());
+
+    if let Ok((mut body, mut visibility)) = body.single_mut() {
+        *visibility = match rig.mode {
+            CameraMode::FirstPerson => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
+        // The body stays upright whatever the pitch of the player
+        let upright = Quat::from_rotation_x(-pitch);
+        body.rotation = upright;
+        body.translation = upright * Vec3::new(0.0, -0.7, 0.0);
+    }
+}
@@ -0,0 +1,277 @@
+//! An overlay showing what the engine is doing, toggled with F3 like in Minecraft. The numbers it
+//! shows are published as diagnostics too, so they can be logged or read by other tools.
+
+use std::fmt::Write;
+
+use bevy::{
+    diagnostic::{
+        Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, FrameTimeDiagnosticsPlugin,
+        RegisterDiagnostic,
+    },
+    prelude::*,
+    render::{render_resource::WgpuFeatures, renderer::RenderDevice},
+};
+
+use super::player::Player;
+use super::world::{ChunkMaterial, VxWorld, VxWorldCoord};
+use super::CHUNK_SIZE;
+
+pub const CHUNK_COUNT: DiagnosticPath = DiagnosticPath::const_new("voxel/chunks");
+pub const VERTEX_COUNT: DiagnosticPath = DiagnosticPath::const_new("voxel/vertices");
+pub const INDEX_COUNT: DiagnosticPath = DiagnosticPath::const_new("voxel/indices");
+/// Time taken by the last batch of chunks to mesh, in milliseconds
+pub const MESHING_TIME: DiagnosticPath = DiagnosticPath::const_new("voxel/meshing_time");
+/// Time taken by the generation of the world, in milliseconds
+pub const GENERATION_TIME: DiagnosticPath = DiagnosticPath::const_new("voxel/generation_time");
+
+const OVERLAY_KEY: KeyCode = KeyCode::F3;
+/// Held with F3, like the shortcuts of Minecraft
+const CHUNK_BORDERS_KEY: KeyCode = KeyCode::KeyG;
+const WIREFRAME_KEY: KeyCode = KeyCode::KeyL;
+/// How many chunks around the player get their borders drawn, in each direction
+const CHUNK_BORDERS_RADIUS: i32 = 1;
+
+/// What the debug overlay shows
+#[derive(Debug, Default, Resource)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    pub chunk_borders: bool,
+    pub wireframe: bool,
+}
+
+/// The text of the overlay
+#[derive(Debug, Component)]
+pub struct DebugText;
+
+/// Registers the diagnostics of the overlay, and the frame time ones it shows too
+pub fn register_diagnostics(app: &mut App) {
+    if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+        app.add_plugins(FrameTimeDiagnosticsPlugin::default());
+    }
+    app.register_diagnostic(Diagnostic::new(CHUNK_COUNT))
+        .register_diagnostic(Diagnostic::new(VERTEX_COUNT))
+        .register_diagnostic(Diagnostic::new(INDEX_COUNT))
+        .register_diagnostic(Diagnostic::new(MESHING_TIME).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(GENERATION_TIME).with_suffix("ms"));
+}
+
+pub fn spawn_debug_overlay(mut commands: Commands) {
+    commands.spawn((
+        DebugText,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            display: Display::None,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+    ));
+}
+
+/// F3 shows or hides the overlay, F3 + G the chunk borders and F3 + L the wireframe
+pub fn toggle_debug_overlay(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut text: Query<&mut Node, With<DebugText>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    render_device: Option<Res<RenderDevice>>,
+    // Whether a shortcut was used since F3 was pressed
+    mut shortcut_used: Local<bool>,
+) {
+    if keyboard_input.pressed(OVERLAY_KEY) {
+        if keyboard_input.just_pressed(CHUNK_BORDERS_KEY) {
+            overlay.chunk_borders = !overlay.chunk_borders;
+            *shortcut_used = true;
+        }
+        if keyboard_input.just_pressed(WIREFRAME_KEY) {
+            *shortcut_used = true;
+            let supported = render_device
+                .is_some_and(|device| device.features().contains(WgpuFeatures::POLYGON_MODE_LINE));
+            if supported {
+                overlay.wireframe = !overlay.wireframe;
+                for (_, material) in materials.iter_mut() {
+                    material.wireframe = overlay.wireframe;
+                }
+            } else {
+                warn!("The GPU does not support drawing wireframes");
+            }
+        }
+    }
+    if !keyboard_input.just_released(OVERLAY_KEY) {
+        return;
+    }
+    // The shortcuts do not toggle the overlay
+    if std::mem::take(&mut *shortcut_used) {
+        return;
+    }
+    overlay.visible = !overlay.visible;
+    for mut node in &mut text {
+        node.display = match overlay.visible {
+            true => Display::Flex,
+            false => Display::None,
+        };
+    }
+}
+
+/// Publishes the statistics of the world as diagnostics, while the overlay shows them
+pub fn measure_world(
+    overlay: Res<DebugOverlay>,
+    my_world: Option<Res<VxWorld>>,
+    meshes: Res<Assets<Mesh>>,
+    mut diagnostics: Diagnostics,
+) {
+    let Some(my_world) = my_world.filter(|_| overlay.visible) else {
+        return;
+    };
+    let (mut vertices, mut indices) = (0, 0);
+    for mesh in my_world
+        .chunk_meshes()
+        .iter()
+        .filter_map(|handle| meshes.get(handle))
+    {
+        vertices += mesh.count_vertices();
+        indices += mesh.indices().map_or(0, |indices| indices.len());
+    }
+    let (meshing_time, _) = my_world.meshing_time();
+    diagnostics.add_measurement(&CHUNK_COUNT, || my_world.chunk_meshes().len() as f64);
+    diagnostics.add_measurement(&VERTEX_COUNT, || vertices as f64);
+    diagnostics.add_measurement(&INDEX_COUNT, || indices as f64);
+    diagnostics.add_measurement(&MESHING_TIME, || meshing_time.as_secs_f64() * 1000.0);
+    diagnostics.add_measurement(&GENERATION_TIME, || {
+        my_world.generation_time().as_secs_f64() * 1000.0
+    });
+}
+
+// The cardinal direction closest to a yaw, forward being north at 0
+fn cardinal(yaw: f32) -> &'static str {
+    let quarter = (yaw / std::f32::consts::FRAC_PI_2).round() as i32;
+    ["north", "west", "south", "east"][quarter.rem_euclid(4) as usize]
+}
+
+pub fn update_debug_text(
+    overlay: Res<DebugOverlay>,
+    store: Res<DiagnosticsStore>,
+    my_world: Option<Res<VxWorld>>,
+    player: Query<&Transform, With<Player>>,
+    mut text: Query<&mut Text, With<DebugText>>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let (Ok(mut text), Ok(transform)) = (text.single_mut(), player.single()) else {
+        return;
+    };
+    let value = |path: &DiagnosticPath| {
+        store
+            .get(path)
+            .and_then(Diagnostic::smoothed)
+            .unwrap_or_default()
+    };
+    let mut lines = String::new();
+    let _ = writeln!(lines, "{:.0} fps", value(&FrameTimeDiagnosticsPlugin::FPS));
+
+    let position = transform.translation;
+    let _ = writeln!(
+        lines,
+        "XYZ: {:.2} / {:.2} / {:.2}",
+        position.x, position.y, position.z
+    );
+    // A voxel spans half a block around its position
+    let block = (position + Vec3::splat(0.5)).floor().as_ivec3();
+    let _ = writeln!(lines, "Block: {} {} {}", block.x, block.y, block.z);
+    match VxWorldCoord::from_position(block) {
+        Some(world_coord) => {
+            let (chunk, local) = (world_coord.chunk_coord(), world_coord.cube_coord());
+            let _ = writeln!(
+                lines,
+                "Chunk: {} {} {} (#{}), in chunk: {} {} {}",
+                chunk.0,
+                chunk.1,
+                chunk.2,
+                world_coord.chunk_id(),
+                local.0,
+                local.1,
+                local.2
+            );
+        }
+        None => {
+            let _ = writeln!(lines, "Chunk: outside of the world");
+        }
+    }
+    let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    let _ = writeln!(
+        lines,
+        "Facing: {} (yaw {:.1}, pitch {:.1})",
+        cardinal(yaw),
+        yaw.to_degrees(),
+        pitch.to_degrees()
+    );
+
+    let _ = writeln!(
+        lines,
+        "Chunks: {:.0}, vertices: {:.0}, indices: {:.0}",
+        value(&CHUNK_COUNT),
+        value(&VERTEX_COUNT),
+        value(&INDEX_COUNT)
+    );
+    if let Some(my_world) = my_world {
+        let (meshing_time, chunks) = my_world.meshing_time();
+        let _ = writeln!(
+            lines,
+            "Last meshing: {chunks} chunks in {:.2} ms",
+            meshing_time.as_secs_f64() * 1000.0
+        );
+        let _ = writeln!(
+            lines,
+            "Generation: {:.0} ms",
+            my_world.generation_time().as_secs_f64() * 1000.0
+        );
+    }
+    let _ = write!(
+        lines,
+        "Chunk borders (F3 + G): {}, wireframe (F3 + L): {}",
+        overlay.chunk_borders, overlay.wireframe
+    );
+    text.0 = lines;
+}
+
+/// Outlines the chunk of the player, and the corners of the chunks around it
+pub fn draw_chunk_borders(
+    overlay: Res<DebugOverlay>,
+    player: Query<&Transform, With<Player>>,
+    mut gizmos: Gizmos,
+) {
+    if !overlay.chunk_borders {
+        return;
+    }
+    let Ok(transform) = player.single() else {
+        return;
+    };
+    let size = CHUNK_SIZE as f32;
+    // The voxels of a chunk span half a block around their positions
+    let chunk = ((transform.translation + Vec3::splat(0.5)) / size).floor();
+    let corner = chunk * size - Vec3::splat(0.5);
+    gizmos.cuboid(
+        Transform::from_translation(corner + Vec3::splat(size / 2.0)).with_scale(Vec3::splat(size)),
+        Color::srgb(1.0, 1.0, 0.0),
+    );
+    let radius = CHUNK_BORDERS_RADIUS + 1;
+    for x in -CHUNK_BORDERS_RADIUS..=radius {
+        for z in -CHUNK_BORDERS_RADIUS..=radius {
+            let bottom = corner + Vec3::new(x as f32, 0.0, z as f32) * size;
+            gizmos.line(
+                bottom - Vec3::Y * size * radius as f32,
+                bottom + Vec3::Y * size * (radius + 1) as f32,
+                Color::srgb(1.0, 0.0, 0.0),
+            );
+        }
+    }
+}
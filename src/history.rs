@@ -0,0 +1,295 @@
+//! The undo history of the world edits. Every voxel replaced through [VxWorld::edit_voxels] or
+//! [VxWorld::edit_voxel], such as the blocks broken, placed, blown up and the pasted or imported
+//! structures, is recorded along with the voxel it replaced and the data of its block entity, the
+//! edits made while a button is held forming a single stroke undone at once. Undoing and redoing
+//! write the voxels back like any other edit, so the chunks are remeshed as usual. The blocks
+//! changed by the world itself, such as the ticks and the falling blocks, are not recorded.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::input::{Action, ActionState};
+use super::player::{GameMode, Player};
+use super::world::{BlockEntities, BlockEntityData, ChestContents, SignText, Voxel, VxWorld};
+
+/// Strokes kept in the history by default
+const HISTORY_DEPTH: usize = 100;
+/// Undoes with Ctrl+Z and redoes with Ctrl+Y
+const UNDO_KEY: KeyCode = KeyCode::KeyZ;
+const REDO_KEY: KeyCode = KeyCode::KeyY;
+
+/// A voxel changed, from `before` to `after`
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub position: IVec3,
+    pub before: Voxel,
+    pub after: Voxel,
+    /// What the block entity of `before` held, such as the content of a chest
+    pub block_entity: Option<BlockEntityData>,
+}
+
+/// The strokes that can be undone, from the oldest, and those undone that can be redone
+#[derive(Debug, Resource)]
+pub struct EditHistory {
+    /// Strokes kept, the oldest ones being forgotten beyond
+    pub depth: usize,
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    // The edits of the stroke in progress
+    stroke: Vec<Edit>,
+    // The data to give back to the block entities brought back by an undo
+    restored: Vec<(IVec3, BlockEntityData)>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_depth(HISTORY_DEPTH)
+    }
+}
+
+impl EditHistory {
+    pub fn with_depth(depth: usize) -> Self {
+        Self {
+            depth,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: Vec::new(),
+            restored: Vec::new(),
+        }
+    }
+
+    /// Adds an edit already made to the stroke in progress. What was undone can no longer be
+    /// redone.
+    pub fn record(&mut self, edit: Edit) {
+        self.stroke.push(edit);
+        self.redo.clear();
+    }
+
+    /// Ends the stroke in progress, which becomes a single step of the history
+    pub fn end_stroke(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        self.forget_oldest();
+    }
+
+    // Keeps the history within its depth
+    fn forget_oldest(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Puts back the voxels of the last stroke as they were before it. Returns false if there is
+    /// nothing to undo.
+    pub fn undo(&mut self, my_world: &mut VxWorld) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.undo.pop_back() else {
+            return false;
+        };
+        // From the last edit, so a voxel edited twice gets its very first value back
+        my_world.set_voxels(stroke.iter().rev().map(|edit| (edit.position, edit.before)));
+        self.restored.extend(stroke.iter().rev().filter_map(|edit| {
+            let data = edit.block_entity.clone()?;
+            Some((edit.position, data))
+        }));
+        self.redo.push(stroke);
+        true
+    }
+
+    /// Makes the last stroke undone again. Returns false if there is nothing to redo.
+    pub fn redo(&mut self, my_world: &mut VxWorld) -> bool {
+        let Some(stroke) = self.redo.pop() else {
+            return false;
+        };
+        my_world.set_voxels(stroke.iter().map(|edit| (edit.position, edit.after)));
+        self.undo.push_back(stroke);
+        self.forget_oldest();
+        true
+    }
+}
+
+/// Records the voxels edited this frame. Runs before the block entities are synchronized, so
+/// those of the replaced voxels can still be read. A stroke lasts as long as the break or the
+/// place button is held.
+pub fn record_edits(
+    actions: Res<ActionState>,
+    mut my_world: ResMut<VxWorld>,
+    block_entities: Res<BlockEntities>,
+    q_data: Query<(Option<&ChestContents>, Option<&SignText>)>,
+    mut history: ResMut<EditHistory>,
+) {
+    for edit in my_world.take_voxel_edits() {
+        let block_entity = block_entities
+            .get(edit.position)
+            .filter(|_| edit.before.cube_type.has_block_entity())
+            .and_then(|entity| q_data.get(entity).ok())
+            .and_then(|(contents, text)| match (contents, text) {
+                (Some(contents), _) => Some(BlockEntityData::Chest(contents.clone())),
+                (_, Some(text)) => Some(BlockEntityData::Sign(text.clone())),
+                _ => None,
+            });
+        history.record(Edit {
+            position: edit.position,
+            before: edit.before,
+            after: edit.after,
+            block_entity,
+        });
+    }
+    if !actions.pressed(Action::Break) && !actions.pressed(Action::Place) {
+        history.end_stroke();
+    }
+}
+
+/// Undoes and redoes the strokes in creative mode, where the blocks come for free
+pub fn undo_edits(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player: Query<&GameMode, With<Player>>,
+    mut history: ResMut<EditHistory>,
+    mut my_world: ResMut<VxWorld>,
+) {
+    let control = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !control
+        || !player
+            .single()
+            .is_ok_and(|game_mode| *game_mode == GameMode::Creative)
+    {
+        return;
+    }
+    if keyboard_input.just_pressed(UNDO_KEY) {
+        history.undo(&mut my_world);
+    } else if keyboard_input.just_pressed(REDO_KEY) {
+        history.redo(&mut my_world);
+    }
+}
+
+/// Gives the block entities spawned again by an undo the data they held
+pub fn restore_block_entities(
+    mut commands: Commands,
+    block_entities: Res<BlockEntities>,
+    mut history: ResMut<EditHistory>,
+) {
+    for (position, data) in std::mem::take(&mut history.restored) {
+        if let Some(entity) = block_entities.get(position) {
+            data.insert_into(&mut commands.entity(entity));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::{index_block_entities, sync_block_entities, CubeTypes},
+        CHUNK_VOLUME, WORLD_VOL,
+    };
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<ActionState>()
+            .init_resource::<BlockEntities>()
+            .init_resource::<EditHistory>()
+            .insert_resource(VxWorld::from_voxels(vec![
+                Voxel::default();
+                WORLD_VOL * CHUNK_VOLUME
+            ]))
+            .add_systems(
+                Update,
+                (
+                    record_edits,
+                    sync_block_entities,
+                    restore_block_entities,
+                    index_block_entities,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn edit(app: &mut App, position: IVec3, voxel: Voxel) {
+        app.world_mut()
+            .resource_mut::<VxWorld>()
+            .edit_voxel(position, voxel);
+        app.update();
+    }
+
+    fn undo(app: &mut App) -> bool {
+        let undone = app
+            .world_mut()
+            .resource_scope(|world, mut history: Mut<EditHistory>| {
+                history.undo(&mut world.resource_mut::<VxWorld>())
+            });
+        app.update();
+        undone
+    }
+
+    fn chest_contents(app: &App, position: IVec3) -> Option<ChestContents> {
+        let entity = app.world().resource::<BlockEntities>().get(position)?;
+        app.world().get::<ChestContents>(entity).cloned()
+    }
+
+    #[test]
+    fn edits_are_undone_stroke_by_stroke() {
+        let mut app = app();
+        let position = IVec3::new(5, 10, 5);
+        edit(&mut app, position, CubeTypes::Stone.into());
+        // Both edits of a single stroke
+        app.world_mut().resource_mut::<VxWorld>().edit_voxels([
+            (position, CubeTypes::Sand.into()),
+            (position + IVec3::X, CubeTypes::Sand.into()),
+        ]);
+        app.update();
+
+        assert!(undo(&mut app));
+        let my_world = app.world().resource::<VxWorld>();
+        assert_eq!(my_world.get_voxel(position).cube_type, CubeTypes::Stone);
+        assert_eq!(
+            my_world.get_voxel(position + IVec3::X).cube_type,
+            CubeTypes::Empty
+        );
+        assert!(undo(&mut app));
+        assert_eq!(
+            app.world().resource::<VxWorld>().get_voxel(position),
+            Voxel::default()
+        );
+        assert!(!undo(&mut app));
+
+        // The world changing by itself is not recorded
+        app.world_mut()
+            .resource_mut::<VxWorld>()
+            .set_voxel(position, CubeTypes::Stone.into());
+        app.update();
+        assert!(!undo(&mut app));
+    }
+
+    #[test]
+    fn undoing_gives_the_block_entities_their_data_back() {
+        let mut app = app();
+        let position = IVec3::new(5, 10, 5);
+        edit(&mut app, position, CubeTypes::Chest.into());
+        let entity = app
+            .world()
+            .resource::<BlockEntities>()
+            .get(position)
+            .unwrap();
+        app.world_mut().get_mut::<ChestContents>(entity).unwrap().0[3] =
+            Some((CubeTypes::Stone, 5));
+
+        edit(&mut app, position, Voxel::default());
+        assert_eq!(app.world().resource::<BlockEntities>().get(position), None);
+
+        assert!(undo(&mut app));
+        assert_eq!(
+            app.world()
+                .resource::<VxWorld>()
+                .get_voxel(position)
+                .cube_type,
+            CubeTypes::Chest
+        );
+        let contents = chest_contents(&app, position).unwrap();
+        assert_eq!(contents.0[3], Some((CubeTypes::Stone, 5)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Player input as actions rather than keys. Each action is bound to keys, mouse buttons or
+//! gamepad buttons, read from a configuration file and changeable while playing; the gameplay
+//! systems only look at the resulting [ActionState].
+
+use std::{any::TypeId, collections::HashSet, fs, path::Path};
+
+use bevy::{
+    input::mouse::AccumulatedMouseMotion,
+    prelude::*,
+    reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer},
+    scene::ron,
+};
+use serde::de::DeserializeSeed;
+
+/// Where the bindings are read from and written to
+pub const BINDINGS_PATH: &str = "config/input.ron";
+
+/// What the player can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sneak,
+    Break,
+    Place,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Sneak,
+        Action::Break,
+        Action::Place,
+    ];
+}
+
+/// An input an action can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    // Whether two bindings come from the same device, so rebinding one keeps the others
+    fn same_device(&self, other: &Binding) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// The bindings of the actions, and how the camera reacts to the mouse and the gamepad
+#[derive(Debug, Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct InputBindings {
+    pub bindings: Vec<(Action, Binding)>,
+    /// Radians turned for each pixel the mouse moves, along X and Y
+    pub mouse_sensitivity: Vec2,
+    /// Radians turned each second with the right stick pushed all the way, along X and Y
+    pub gamepad_sensitivity: Vec2,
+    /// Looking down when the mouse or the stick goes up
+    pub invert_y: bool,
+    /// How far the sticks must be pushed before they do anything
+    pub dead_zone: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::*;
+        Self {
+            bindings: vec![
+                (Action::MoveForward, Key(KeyCode::KeyW)),
+                (Action::MoveForward, Key(KeyCode::ArrowUp)),
+                (Action::MoveBackward, Key(KeyCode::KeyS)),
+                (Action::MoveBackward, Key(KeyCode::ArrowDown)),
+                (Action::MoveLeft, Key(KeyCode::KeyA)),
+                (Action::MoveLeft, Key(KeyCode::ArrowLeft)),
+                (Action::MoveRight, Key(KeyCode::KeyD)),
+                (Action::MoveRight, Key(KeyCode::ArrowRight)),
+                (Action::Jump, Key(KeyCode::Space)),
+                (Action::Jump, Gamepad(GamepadButton::South)),
+                (Action::Sneak, Key(KeyCode::ShiftLeft)),
+                (Action::Sneak, Gamepad(GamepadButton::East)),
+                (Action::Break, Mouse(MouseButton::Left)),
+                (Action::Break, Gamepad(GamepadButton::RightTrigger2)),
+                (Action::Place, Mouse(MouseButton::Right)),
+                (Action::Place, Gamepad(GamepadButton::LeftTrigger2)),
+            ],
+            mouse_sensitivity: Vec2::new(0.006, 0.004),
+            gamepad_sensitivity: Vec2::new(3.0, 2.0),
+            invert_y: false,
+            dead_zone: 0.15,
+        }
+    }
+}
+
+impl InputBindings {
+    /// The inputs an action is bound to
+    pub fn bindings(&self, action: Action) -> impl Iterator<Item = Binding> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| *bound == action)
+            .map(|(_, binding)| *binding)
+    }
+
+    /// Binds an action to an input, replacing its bindings on the same device. The input is taken
+    /// away from the other actions.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.bindings.retain(|(bound, other)| {
+            *other != binding && !(*bound == action && other.same_device(&binding))
+        });
+        self.bindings.push((action, binding));
+    }
+
+    /// Reads the bindings from a RON file
+    pub fn load(path: impl AsRef<Path>, type_registry: &AppTypeRegistry) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let type_registry = type_registry.read();
+        let registration = type_registry
+            .get(TypeId::of::<Self>())
+            .ok_or("the bindings are not registered")?;
+        let mut deserializer =
+            ron::Deserializer::from_str(&text).map_err(|error| error.to_string())?;
+        let reflected = TypedReflectDeserializer::new(registration, &type_registry)
+            .deserialize(&mut deserializer)
+            .map_err(|error| error.to_string())?;
+        Self::from_reflect(reflected.as_partial_reflect())
+            .ok_or_else(|| "the bindings are incomplete".to_string())
+    }
+
+    /// Writes the bindings to a RON file
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        type_registry: &AppTypeRegistry,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let type_registry = type_registry.read();
+        let serializer = TypedReflectSerializer::new(self.as_partial_reflect(), &type_registry);
+        let text = ron::ser::to_string_pretty(&serializer, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).map_err(|error| error.to_string())?;
+        }
+        fs::write(path, text).map_err(|error| error.to_string())
+    }
+}
+
+/// Set to an action to bind it to the next input pressed
+#[derive(Debug, Default, Resource)]
+pub struct Rebinding(pub Option<Action>);
+
+/// The state of the actions this frame
+#[derive(Debug, Default, Resource)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    /// Where to move, X going right and Y forward, each between -1 and 1. The keys give whole
+    /// values, the left stick anything in between.
+    pub movement: Vec2,
+    /// How much to turn this frame, in radians: the yaw first, then the pitch
+    pub look: Vec2,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+}
+
+/// Reads the bindings from their file, or writes the default ones if there is none yet
+pub fn load_bindings(mut bindings: ResMut<InputBindings>, type_registry: Res<AppTypeRegistry>) {
+    if !Path::new(BINDINGS_PATH).exists() {
+        if let Err(error) = bindings.save(BINDINGS_PATH, &type_registry) {
+            warn!("Could not write {BINDINGS_PATH}: {error}");
+        }
+        return;
+    }
+    match InputBindings::load(BINDINGS_PATH, &type_registry) {
+        Ok(loaded) => *bindings = loaded,
+        Err(error) => warn!("Could not load {BINDINGS_PATH}, using the default bindings: {error}"),
+    }
+}
+
+// Zeroes the small values of a stick, and rescales the others so they still start from zero
+fn dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    stick / length * ((length - dead_zone) / (1.0 - dead_zone)).min(1.0)
+}
+
+/// Turns the raw input into actions
+pub fn update_actions(
+    bindings: Res<InputBindings>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    accumulated_mouse_motion: Res<AccumulatedMouseMotion>,
+    gamepads: Query<&Gamepad>,
+    time: Res<Time>,
+    mut state: ResMut<ActionState>,
+) {
+    let is_pressed = |binding: Binding| match binding {
+        Binding::Key(key) => keyboard_input.pressed(key),
+        Binding::Mouse(button) => mouse_input.pressed(button),
+        Binding::Gamepad(button) => gamepads.iter().any(|gamepad| gamepad.pressed(button)),
+    };
+    let mut pressed = HashSet::new();
+    for action in Action::ALL {
+        if bindings.bindings(action).any(is_pressed) {
+            pressed.insert(action);
+        }
+    }
+    state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+    state.just_released = state.pressed.difference(&pressed).copied().collect();
+    state.pressed = pressed;
+
+    let axis = |positive: Action, negative: Action| {
+        state.pressed(positive) as i32 as f32 - state.pressed(negative) as i32 as f32
+    };
+    let mut movement = Vec2::new(
+        axis(Action::MoveRight, Action::MoveLeft),
+        axis(Action::MoveForward, Action::MoveBackward),
+    );
+    let mut look = -accumulated_mouse_motion.delta * bindings.mouse_sensitivity;
+    for gamepad in &gamepads {
+        movement += dead_zone(gamepad.left_stick(), bindings.dead_zone);
+        // The stick goes up along positive Y, the mouse along negative Y
+        let stick = dead_zone(gamepad.right_stick(), bindings.dead_zone) * Vec2::new(-1.0, 1.0);
+        look += stick * bindings.gamepad_sensitivity * time.delta_secs();
+    }
+    if bindings.invert_y {
+        look.y = -look.y;
+    }
+    state.movement = movement.clamp(Vec2::NEG_ONE, Vec2::ONE);
+    state.look = look;
+}
+
+/// Binds the action waiting in [Rebinding] to the first input pressed, and saves the bindings.
+/// Escape cancels.
+pub fn capture_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    type_registry: Res<AppTypeRegistry>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        rebinding.0 = None;
+        return;
+    }
+    let binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse_input
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepads
+                .iter()
+                .find_map(|gamepad| gamepad.get_just_pressed().next())
+                .map(|button| Binding::Gamepad(*button))
+        });
+    let Some(binding) = binding else {
+        return;
+    };
+    bindings.rebind(action, binding);
+    rebinding.0 = None;
+    if let Err(error) = bindings.save(BINDINGS_PATH, &type_registry) {
+        warn!("Could not write {BINDINGS_PATH}: {error}");
+    }
+}
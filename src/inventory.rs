@@ -0,0 +1,498 @@
+//! The blocks carried by the player: a hotbar of nine slots always on screen, the block of the
+//! selected one being placed, and a larger inventory opened with E.
+
+use bevy::{
+    image::{ImageLoaderSettings, ImageSampler},
+    input::mouse::AccumulatedMouseScroll,
+    prelude::*,
+};
+
+use super::menu::GameState;
+use super::player::{GameMode, Player, EYE_HEIGHT};
+use super::world::{CubeTypes, ItemDrop};
+
+/// Number of slots of the hotbar, the first ones of the inventory
+pub const HOTBAR_SIZE: usize = 9;
+/// Number of slots of the inventory, the hotbar included
+pub const INVENTORY_SIZE: usize = 36;
+/// The largest number of blocks a slot holds
+pub const STACK_SIZE: u32 = 64;
+
+const INVENTORY_KEY: KeyCode = KeyCode::KeyE;
+/// How close to the middle of the body of the player the dropped items are picked up
+const PICK_UP_DISTANCE: f32 = 1.5;
+const SLOT_KEYS: [KeyCode; HOTBAR_SIZE] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+// Side of a slot on screen, in pixels
+const SLOT_SIZE: f32 = 48.0;
+// Number of slots on each line of the inventory screen
+const SCREEN_COLUMNS: usize = 9;
+// The atlas is a grid of 32 by 32 tiles of 16 pixels
+const ATLAS_TILE: u32 = 16;
+const ATLAS_TILES: u32 = 32;
+
+/// Several blocks of the same type held in a slot
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct ItemStack {
+    pub cube_type: CubeTypes,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+    /// The selected slot of the hotbar
+    pub selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: vec![None; INVENTORY_SIZE],
+            selected: 0,
+        }
+    }
+}
+
+impl Inventory {
+    /// An inventory holding a few building blocks in its hotbar
+    pub fn with_hotbar(cube_types: &[CubeTypes]) -> Self {
+        let mut inventory = Self::default();
+        for (slot, cube_type) in inventory.slots.iter_mut().zip(cube_types) {
+            *slot = Some(ItemStack {
+                cube_type: *cube_type,
+                count: STACK_SIZE,
+            });
+        }
+        inventory
+    }
+
+    pub fn selected_stack(&self) -> Option<&ItemStack> {
+        self.slots.get(self.selected)?.as_ref()
+    }
+
+    /// Adds blocks to the stacks of the same type first, then to the empty slots, the hotbar being
+    /// filled before the rest. Returns the number of blocks that did not fit.
+    pub fn add(&mut self, cube_type: CubeTypes, mut count: u32) -> u32 {
+        for stack in self.slots.iter_mut().flatten() {
+            if stack.cube_type == cube_type && stack.count < STACK_SIZE {
+                let added = count.min(STACK_SIZE - stack.count);
+                stack.count += added;
+                count -= added;
+            }
+        }
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if count == 0 {
+                break;
+            }
+            let added = count.min(STACK_SIZE);
+            *slot = Some(ItemStack {
+                cube_type,
+                count: added,
+            });
+            count -= added;
+        }
+        count
+    }
+
+    /// Takes one block out of the selected slot
+    pub fn take_selected(&mut self) -> Option<CubeTypes> {
+        let slot = self.slots.get_mut(self.selected)?;
+        let stack = slot.as_mut()?;
+        let cube_type = stack.cube_type;
+        stack.count -= 1;
+        if stack.count == 0 {
+            *slot = None;
+        }
+        Some(cube_type)
+    }
+
+    /// Selects the next slot of the hotbar, or the previous one for negative steps
+    pub fn scroll(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(HOTBAR_SIZE as i32) as usize;
+    }
+}
+
+/// The texture atlas the icons of the slots are cut from
+#[derive(Debug, Resource)]
+pub struct SlotIcons {
+    image: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
+/// A slot of the inventory shown on screen
+#[derive(Debug, Clone, Copy, Component)]
+pub struct SlotNode(pub usize);
+
+/// The icon of the block held by a slot
+#[derive(Debug, Clone, Copy, Component)]
+pub struct SlotIcon(pub usize);
+
+/// The number of blocks held by a slot
+#[derive(Debug, Clone, Copy, Component)]
+pub struct SlotCount(pub usize);
+
+/// The root node of the inventory screen
+#[derive(Debug, Component)]
+pub struct InventoryScreen;
+
+/// What clicking an entry of the inventory screen does
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum InventoryButton {
+    /// Fills the selected hotbar slot with a full stack of the block, in creative mode
+    Block(CubeTypes),
+    /// Swaps the slot with the selected hotbar slot
+    Slot(usize),
+}
+
+fn slot_node(parent: &mut ChildSpawnerCommands, icons: &SlotIcons, slot: usize) {
+    let mut node = parent.spawn((
+        SlotNode(slot),
+        Node {
+            width: Val::Px(SLOT_SIZE),
+            height: Val::Px(SLOT_SIZE),
+            margin: UiRect::all(Val::Px(2.0)),
+            border: UiRect::all(Val::Px(3.0)),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.2, 0.2, 0.2, 0.7)),
+        BorderColor(Color::srgb(0.4, 0.4, 0.4)),
+    ));
+    // The slots of the inventory screen are clickable, unlike the ones of the hotbar
+    if slot >= HOTBAR_SIZE {
+        node.insert((Button, InventoryButton::Slot(slot)));
+    }
+    node.with_children(|parent| {
+        parent.spawn((
+            SlotIcon(slot),
+            ImageNode::from_atlas_image(
+                icons.image.clone(),
+                TextureAtlas::from(icons.layout.clone()),
+            ),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            Visibility::Hidden,
+        ));
+        parent.spawn((
+            SlotCount(slot),
+            Text::default(),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(2.0),
+                bottom: Val::Px(0.0),
+                ..default()
+            },
+        ));
+    });
+}
+
+/// Loads the icons, and spawns the hotbar at the bottom of the screen
+pub fn spawn_hotbar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let icons = SlotIcons {
+        image: asset_server.load_with_settings(
+            "textures.png",
+            |settings: &mut ImageLoaderSettings| {
+                settings.sampler = ImageSampler::nearest();
+            },
+        ),
+        layout: layouts.add(TextureAtlasLayout::from_grid(
+            UVec2::splat(ATLAS_TILE),
+            ATLAS_TILES,
+            ATLAS_TILES,
+            None,
+            None,
+        )),
+    };
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            for slot in 0..HOTBAR_SIZE {
+                slot_node(parent, &icons, slot);
+            }
+        });
+    commands.insert_resource(icons);
+}
+
+/// Picks the slot of the hotbar with the number keys or the mouse wheel
+pub fn select_slot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    accumulated_mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut player: Query<&mut Inventory, With<Player>>,
+) {
+    let Ok(mut inventory) = player.single_mut() else {
+        return;
+    };
+    if let Some(slot) = SLOT_KEYS
+        .iter()
+        .position(|key| keyboard_input.just_pressed(*key))
+    {
+        inventory.selected = slot;
+    }
+    // Scrolling down moves to the right, as in most games
+    let scroll = accumulated_mouse_scroll.delta.y;
+    if scroll != 0.0 {
+        inventory.scroll(-scroll.signum() as i32);
+    }
+}
+
+/// Shows the content of the inventory in its slots
+pub fn update_slots(
+    player: Query<&Inventory, (With<Player>, Changed<Inventory>)>,
+    mut nodes: Query<(&SlotNode, &mut BorderColor)>,
+    mut icons: Query<(&SlotIcon, &mut ImageNode, &mut Visibility)>,
+    mut counts: Query<(&SlotCount, &mut Text)>,
+) {
+    let Ok(inventory) = player.single() else {
+        return;
+    };
+    for (node, mut border) in &mut nodes {
+        border.0 = match node.0 == inventory.selected {
+            true => Color::WHITE,
+            false => Color::srgb(0.4, 0.4, 0.4),
+        };
+    }
+    for (icon, mut image, mut visibility) in &mut icons {
+        let tile = inventory.slots[icon.0].and_then(|stack| stack.cube_type.icon());
+        match (tile, image.texture_atlas.as_mut()) {
+            (Some(tile), Some(atlas)) => {
+                atlas.index = tile;
+                *visibility = Visibility::Inherited;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+    for (count, mut text) in &mut counts {
+        text.0 = match inventory.slots[count.0] {
+            Some(stack) if stack.count > 1 => stack.count.to_string(),
+            _ => String::new(),
+        };
+    }
+}
+
+/// Puts the dropped items close to the player into their inventory, as long as it has room.
+/// Spectators leave them where they are.
+pub fn pick_up_items(
+    mut commands: Commands,
+    mut player: Query<(&Transform, &GameMode, &mut Inventory), With<Player>>,
+    items: Query<(Entity, &Transform, &ItemDrop), Without<Player>>,
+) {
+    let Ok((transform, game_mode, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    if !game_mode.can_edit() {
+        return;
+    }
+    let body = transform.translation - Vec3::Y * EYE_HEIGHT / 2.0;
+    for (entity, item_transform, item) in &items {
+        if item_transform.translation.distance(body) < PICK_UP_DISTANCE
+            && inventory.add(item.0, 1) == 0
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// E opens the inventory screen, or closes it
+pub fn toggle_inventory(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard_input.just_pressed(INVENTORY_KEY) {
+        return;
+    }
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Inventory),
+        GameState::Inventory => next_state.set(GameState::Playing),
+        GameState::Paused | GameState::Dead => {}
+    }
+}
+
+/// Lists every block in creative and spectator modes, and the slots out of the hotbar in survival
+/// mode
+pub fn spawn_inventory_screen(
+    mut commands: Commands,
+    icons: Res<SlotIcons>,
+    mut player: Query<(&GameMode, &mut Inventory), With<Player>>,
+) {
+    let Ok((game_mode, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    // Shows the slots of the new screen
+    inventory.set_changed();
+    commands
+        .spawn((
+            InventoryScreen,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    width: Val::Px((SLOT_SIZE + 4.0) * SCREEN_COLUMNS as f32),
+                    flex_wrap: FlexWrap::Wrap,
+                    ..default()
+                })
+                .with_children(|parent| match game_mode {
+                    GameMode::Creative | GameMode::Spectator => {
+                        for cube_type in CubeTypes::ALL {
+                            let Some(tile) = cube_type.icon() else {
+                                continue;
+                            };
+                            let mut image = ImageNode::from_atlas_image(
+                                icons.image.clone(),
+                                TextureAtlas::from(icons.layout.clone()),
+                            );
+                            if let Some(atlas) = image.texture_atlas.as_mut() {
+                                atlas.index = tile;
+                            }
+                            parent
+                                .spawn((
+                                    InventoryButton::Block(cube_type),
+                                    Button,
+                                    Node {
+                                        width: Val::Px(SLOT_SIZE),
+                                        height: Val::Px(SLOT_SIZE),
+                                        margin: UiRect::all(Val::Px(2.0)),
+                                        padding: UiRect::all(Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    BackgroundColor(Color::srgba(0.2, 0.2, 0.2, 0.7)),
+                                ))
+                                .with_child((
+                                    image,
+                                    Node {
+                                        width: Val::Percent(100.0),
+                                        height: Val::Percent(100.0),
+                                        ..default()
+                                    },
+                                ));
+                        }
+                    }
+                    GameMode::Survival => {
+                        for slot in HOTBAR_SIZE..INVENTORY_SIZE {
+                            slot_node(parent, &icons, slot);
+                        }
+                    }
+                });
+        });
+}
+
+pub fn despawn_inventory_screen(
+    mut commands: Commands,
+    screen: Query<Entity, With<InventoryScreen>>,
+) {
+    for entity in &screen {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn press_inventory_buttons(
+    buttons: Query<(&Interaction, &InventoryButton), Changed<Interaction>>,
+    mut player: Query<&mut Inventory, With<Player>>,
+) {
+    let Ok(mut inventory) = player.single_mut() else {
+        return;
+    };
+    let selected = inventory.selected;
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            InventoryButton::Block(cube_type) => {
+                inventory.slots[selected] = Some(ItemStack {
+                    cube_type,
+                    count: STACK_SIZE,
+                });
+            }
+            InventoryButton::Slot(slot) => inventory.slots.swap(selected, slot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(cube_type: CubeTypes, count: u32) -> Option<ItemStack> {
+        Some(ItemStack { cube_type, count })
+    }
+
+    #[test]
+    fn added_blocks_fill_the_stacks_then_the_empty_slots() {
+        let mut inventory = Inventory::default();
+        inventory.slots[2] = stack(CubeTypes::Stone, 60);
+        inventory.slots[20] = stack(CubeTypes::Stone, 10);
+        inventory.slots[0] = stack(CubeTypes::Sand, 1);
+        assert_eq!(inventory.add(CubeTypes::Stone, 120), 0);
+        assert_eq!(inventory.slots[2], stack(CubeTypes::Stone, STACK_SIZE));
+        assert_eq!(inventory.slots[20], stack(CubeTypes::Stone, STACK_SIZE));
+        // The first empty slot, in the hotbar, takes the rest
+        assert_eq!(inventory.slots[1], stack(CubeTypes::Stone, 62));
+        assert_eq!(inventory.slots[0], stack(CubeTypes::Sand, 1));
+        assert!(inventory.slots[3..20].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn blocks_that_do_not_fit_are_returned() {
+        let mut inventory = Inventory::default();
+        let capacity = INVENTORY_SIZE as u32 * STACK_SIZE;
+        assert_eq!(inventory.add(CubeTypes::Dirt, capacity + 5), 5);
+        assert!(inventory
+            .slots
+            .iter()
+            .all(|slot| *slot == stack(CubeTypes::Dirt, STACK_SIZE)));
+        assert_eq!(inventory.add(CubeTypes::Dirt, 1), 1);
+        assert_eq!(inventory.add(CubeTypes::Sand, 3), 3);
+    }
+
+    #[test]
+    fn taking_the_last_block_empties_the_slot() {
+        let mut inventory = Inventory::default();
+        inventory.slots[4] = stack(CubeTypes::Gravel, 2);
+        inventory.selected = 4;
+        assert_eq!(inventory.take_selected(), Some(CubeTypes::Gravel));
+        assert_eq!(inventory.slots[4], stack(CubeTypes::Gravel, 1));
+        assert_eq!(inventory.take_selected(), Some(CubeTypes::Gravel));
+        assert_eq!(inventory.slots[4], None);
+        assert_eq!(inventory.take_selected(), None);
+        inventory.selected = INVENTORY_SIZE;
+        assert_eq!(inventory.take_selected(), None);
+    }
+}
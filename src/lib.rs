@@ -1,11 +1,23 @@
-use bevy::{
-    prelude::*,
-    window::{CursorGrabMode, PrimaryWindow},
+use bevy::prelude::*;
+use menu::GameState;
+use world::{
+    BlockEntities, BlockEntity, BlockNames, BlockTicks, ChestContents, ChunkMaterial,
+    ChunkRemeshed, SignText, TerrainGenerator, UndergroundConfig, VoxPaletteMapping, TICK_RATE,
 };
-use world::ChunkMaterial;
 
-mod player;
-mod world;
+pub mod camera;
+pub mod debug;
+pub mod history;
+pub mod input;
+pub mod inventory;
+pub mod menu;
+pub mod minimap;
+pub mod particles;
+pub mod player;
+pub mod sound;
+pub mod survival;
+pub mod target;
+pub mod world;
 
 pub struct BevyVoxelPlugin;
 
@@ -17,32 +29,195 @@ pub const WORLD_VOL: usize = WORLD_AREA * WORLD_H;
 pub const CHUNK_SIZE: usize = 32;
 pub const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
 pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_AREA;
+pub const WORLD_SEED: u64 = 0x5EED;
 
-pub const PLAYER_POS: Vec3 = Vec3::new(
-    (WORLD_W * CHUNK_SIZE) as f32 / 2_f32,
-    48.0,
-    (WORLD_D * CHUNK_SIZE) as f32 / 2_f32,
+/// The column around which the player spawns, at the center of the world
+pub const SPAWN_COLUMN: IVec2 = IVec2::new(
+    (WORLD_W * CHUNK_SIZE) as i32 / 2,
+    (WORLD_D * CHUNK_SIZE) as i32 / 2,
 );
 
 impl Plugin for BevyVoxelPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(MaterialPlugin::<ChunkMaterial>::default());
         app.insert_resource(ClearColor(Color::srgb(0.5, 0.5, 0.9)));
+        app.init_resource::<BlockEntities>();
+        app.init_resource::<BlockTicks>()
+            .insert_resource(Time::<Fixed>::from_hz(TICK_RATE));
+        app.init_resource::<UndergroundConfig>();
+        app.init_resource::<TerrainGenerator>();
+        app.init_resource::<BlockNames>();
+        app.init_resource::<VoxPaletteMapping>();
+        app.init_resource::<target::Target>();
+        app.init_resource::<player::BreakProgress>();
+        app.init_resource::<player::SpawnPoint>();
+        app.init_resource::<history::EditHistory>();
+        app.init_state::<GameState>();
+        app.init_resource::<menu::GameSettings>();
+        app.init_resource::<debug::DebugOverlay>();
+        app.init_resource::<camera::CameraRig>();
+        app.init_resource::<input::InputBindings>()
+            .init_resource::<input::ActionState>()
+            .init_resource::<input::Rebinding>();
+        app.add_event::<ChunkRemeshed>()
+            .add_event::<player::Landed>()
+            .add_event::<player::BlockBroken>()
+            .add_event::<player::BlockPlaced>()
+            .add_event::<sound::BlockSoundEvent>()
+            .add_event::<world::Explosion>()
+            .add_event::<world::Exploded>();
+        debug::register_diagnostics(app);
+        app.register_type::<input::InputBindings>()
+            .register_type::<inventory::Inventory>()
+            .register_type::<player::GameMode>()
+            .register_type::<player::PlayerSave>();
+        app.register_type::<BlockEntity>()
+            .register_type::<ChestContents>()
+            .register_type::<SignText>();
         app.add_systems(
             Startup,
             (
-                cursor_grab,
+                input::load_bindings,
                 player::spawn_view_model,
                 world::spawn_world_model,
+                world::load_block_entities,
+                world::init_cube_meshes,
+                minimap::spawn_minimap,
+                target::spawn_crack_overlay,
+                debug::spawn_debug_overlay,
+                inventory::spawn_hotbar,
+                survival::spawn_survival_bars,
+                particles::spawn_particle_pool,
+                player::place_player
+                    .after(player::spawn_view_model)
+                    .after(world::spawn_world_model),
             ),
         );
-        app.add_systems(Update, (player::rotate_player, player::move_player));
+        app.add_systems(
+            PreUpdate,
+            (input::capture_rebinding, input::update_actions)
+                .chain()
+                .after(bevy::input::InputSystem),
+        );
+        app.add_systems(OnEnter(GameState::Playing), menu::grab_cursor);
+        app.add_systems(
+            OnEnter(GameState::Paused),
+            (menu::release_cursor, menu::spawn_pause_menu),
+        );
+        app.add_systems(OnExit(GameState::Paused), menu::despawn_pause_menu);
+        app.add_systems(
+            OnEnter(GameState::Inventory),
+            (menu::release_cursor, inventory::spawn_inventory_screen),
+        );
+        app.add_systems(
+            OnExit(GameState::Inventory),
+            inventory::despawn_inventory_screen,
+        );
+        app.add_systems(
+            OnEnter(GameState::Dead),
+            (menu::release_cursor, survival::spawn_death_screen),
+        );
+        app.add_systems(OnExit(GameState::Dead), survival::despawn_death_screen);
+        app.add_systems(
+            Update,
+            (
+                menu::toggle_pause,
+                (menu::press_menu_buttons, menu::update_menu)
+                    .chain()
+                    .run_if(in_state(GameState::Paused)),
+                menu::apply_settings,
+                (
+                    inventory::toggle_inventory,
+                    inventory::press_inventory_buttons.run_if(in_state(GameState::Inventory)),
+                    inventory::update_slots,
+                )
+                    .chain(),
+                (
+                    (player::rotate_player, inventory::select_slot).run_if(camera::not_orbiting),
+                    player::move_player,
+                    player::switch_game_mode,
+                    camera::switch_camera_mode,
+                    camera::control_orbit,
+                    menu::regrab_cursor,
+                )
+                    .run_if(in_state(GameState::Playing)),
+                (
+                    survival::take_fall_damage,
+                    survival::drown,
+                    survival::touch_hazards,
+                    survival::regenerate,
+                    survival::die,
+                )
+                    .chain()
+                    .after(player::move_player)
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(survival::in_survival),
+                (
+                    survival::press_respawn_button.run_if(in_state(GameState::Dead)),
+                    survival::update_survival_bars,
+                )
+                    .chain(),
+                camera::update_camera
+                    .after(player::move_player)
+                    .after(player::rotate_player)
+                    .after(camera::control_orbit),
+                (
+                    target::update_target,
+                    (player::break_block, player::place_block)
+                        .chain()
+                        .run_if(in_state(GameState::Playing)),
+                    world::explode,
+                    history::record_edits,
+                    history::undo_edits.run_if(in_state(GameState::Playing)),
+                    // The falling blocks stand still too while the game is paused
+                    (world::start_falling, world::fall)
+                        .chain()
+                        .run_if(not(in_state(GameState::Paused))),
+                    world::remesh_chunks,
+                    world::sync_block_entities,
+                    history::restore_block_entities,
+                    world::index_block_entities,
+                    target::update_crack_overlay,
+                    target::draw_target_outline,
+                )
+                    .chain()
+                    .after(player::move_player)
+                    .after(player::rotate_player),
+                (
+                    inventory::pick_up_items.run_if(in_state(GameState::Playing)),
+                    world::spin_item_drops,
+                )
+                    .after(world::fall),
+                (sound::emit_block_sounds, sound::play_block_sounds)
+                    .chain()
+                    .after(player::place_block)
+                    .after(player::move_player),
+                (particles::emit_particles, particles::update_particles)
+                    .chain()
+                    .after(world::explode)
+                    .after(camera::update_camera),
+                (
+                    (minimap::toggle_map, minimap::zoom_map).run_if(in_state(GameState::Playing)),
+                    minimap::update_map_image,
+                    minimap::follow_player,
+                )
+                    .chain()
+                    .after(world::remesh_chunks),
+                (
+                    debug::toggle_debug_overlay,
+                    debug::measure_world,
+                    debug::update_debug_text,
+                    debug::draw_chunk_borders,
+                )
+                    .chain()
+                    .after(world::remesh_chunks),
+            ),
+        );
+        app.add_systems(
+            FixedUpdate,
+            // The world stands still while the game is paused
+            world::run_block_ticks.run_if(not(in_state(GameState::Paused))),
+        );
+        app.add_systems(Last, (world::save_world, player::save_player));
     }
 }
-
-fn cursor_grab(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
-    let mut primary_window = q_windows.single_mut().unwrap();
-
-    primary_window.cursor_options.grab_mode = CursorGrabMode::Locked;
-    primary_window.cursor_options.visible = false;
-}
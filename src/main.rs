@@ -3,6 +3,8 @@ use bevy_voxel::BevyVoxelPlugin;
 
 fn main() {
     App::new()
+        // The renderer asks for every feature the adapter supports, so the debug overlay draws
+        // the chunks in wireframe only where polygon lines are available
         .add_plugins(DefaultPlugins)
         .add_plugins(BevyVoxelPlugin)
         .run();
@@ -0,0 +1,413 @@
+//! The pause menu, and the grab of the cursor: locked while playing, free in the menu or when the
+//! window loses the focus.
+
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow, WindowFocused},
+};
+
+use super::input::{Action, Binding, InputBindings, Rebinding, BINDINGS_PATH};
+use super::CHUNK_SIZE;
+
+const PAUSE_KEY: KeyCode = KeyCode::Escape;
+const FOV_STEP: f32 = 5.0;
+const FOV_RANGE: (f32, f32) = (50.0, 120.0);
+const SENSITIVITY_STEP: f32 = 1.25;
+const RENDER_DISTANCE_RANGE: (u32, u32) = (2, 32);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, States)]
+pub enum GameState {
+    #[default]
+    Playing,
+    Paused,
+    /// The inventory screen is open
+    Inventory,
+    /// The player died, and waits to respawn
+    Dead,
+}
+
+/// The settings changed from the menu
+#[derive(Debug, Clone, Resource)]
+pub struct GameSettings {
+    /// The vertical field of view, in degrees
+    pub fov: f32,
+    /// How far the world is drawn, in chunks
+    pub render_distance: u32,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            fov: 90.0,
+            render_distance: 16,
+        }
+    }
+}
+
+/// The root node of the menu
+#[derive(Debug, Component)]
+pub struct PauseMenu;
+
+/// The pages of the menu, only one being shown at once
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum MenuPage {
+    Main,
+    Settings,
+    /// The bindings of the actions
+    Controls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum MenuButton {
+    Resume,
+    Settings,
+    Controls,
+    Back,
+    Quit,
+    /// Waits for the input to bind the action to
+    Rebind(Action),
+    /// Adds to the field of view, in degrees
+    Fov(f32),
+    /// Multiplies the sensitivity of the mouse and the gamepad
+    Sensitivity(f32),
+    /// Adds to the render distance, in chunks
+    RenderDistance(i32),
+}
+
+/// The text showing the value of a setting
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum SettingText {
+    Fov,
+    Sensitivity,
+    RenderDistance,
+}
+
+/// The text showing the inputs an action is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct BindingText(pub Action);
+
+fn set_cursor_grab(window: &mut Window, grab: bool) {
+    window.cursor_options.grab_mode = match grab {
+        true => CursorGrabMode::Locked,
+        false => CursorGrabMode::None,
+    };
+    window.cursor_options.visible = !grab;
+}
+
+pub fn grab_cursor(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut primary_window) = q_windows.single_mut() {
+        set_cursor_grab(&mut primary_window, true);
+    }
+}
+
+pub fn release_cursor(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut primary_window) = q_windows.single_mut() {
+        set_cursor_grab(&mut primary_window, false);
+    }
+}
+
+/// While playing, lets the cursor go when the window loses the focus, and takes it back when the
+/// window gets it back or is clicked
+pub fn regrab_cursor(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut q_windows: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+) {
+    let Ok((entity, mut primary_window)) = q_windows.single_mut() else {
+        return;
+    };
+    for event in focus_events.read().filter(|event| event.window == entity) {
+        set_cursor_grab(&mut primary_window, event.focused);
+    }
+    let grabbed = primary_window.cursor_options.grab_mode != CursorGrabMode::None;
+    if !grabbed && mouse.get_just_pressed().next().is_some() {
+        set_cursor_grab(&mut primary_window, true);
+    }
+}
+
+/// Escape opens the menu, or closes it along with the other screens
+pub fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    rebinding: Res<Rebinding>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    // Escape cancels the rebinding of an action instead, which may have happened this frame
+    if !keyboard_input.just_pressed(PAUSE_KEY) || rebinding.0.is_some() || rebinding.is_changed() {
+        return;
+    }
+    next_state.set(match state.get() {
+        GameState::Playing => GameState::Paused,
+        GameState::Paused | GameState::Inventory => GameState::Playing,
+        // Only the respawn button leaves the death screen
+        GameState::Dead => return,
+    });
+}
+
+fn button(parent: &mut ChildSpawnerCommands, label: &str, action: MenuButton, width: f32) {
+    parent
+        .spawn((
+            action,
+            Button,
+            Node {
+                width: Val::Px(width),
+                height: Val::Px(40.0),
+                margin: UiRect::all(Val::Px(4.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+        ))
+        .with_child(Text::new(label));
+}
+
+// A line of the settings page: the value between a button lowering it and a button raising it
+fn setting(
+    parent: &mut ChildSpawnerCommands,
+    text: SettingText,
+    lower: MenuButton,
+    raise: MenuButton,
+) {
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            button(parent, "-", lower, 40.0);
+            parent.spawn((
+                text,
+                Text::default(),
+                Node {
+                    width: Val::Px(260.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                TextLayout::new_with_justify(JustifyText::Center),
+            ));
+            button(parent, "+", raise, 40.0);
+        });
+}
+
+// A line of the controls page: the action, and a button showing its inputs to rebind it
+fn control(parent: &mut ChildSpawnerCommands, action: Action) {
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format!("{action:?}")),
+                Node {
+                    width: Val::Px(160.0),
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    MenuButton::Rebind(action),
+                    Button,
+                    Node {
+                        width: Val::Px(260.0),
+                        height: Val::Px(32.0),
+                        margin: UiRect::all(Val::Px(2.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                ))
+                .with_child((BindingText(action), Text::default()));
+        });
+}
+
+pub fn spawn_pause_menu(mut commands: Commands) {
+    let page = |page: MenuPage| {
+        (
+            page,
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                display: match page {
+                    MenuPage::Main => Display::Flex,
+                    MenuPage::Settings | MenuPage::Controls => Display::None,
+                },
+                ..default()
+            },
+        )
+    };
+    commands
+        .spawn((
+            PauseMenu,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        ))
+        .with_children(|parent| {
+            parent.spawn(page(MenuPage::Main)).with_children(|parent| {
+                button(parent, "Resume", MenuButton::Resume, 220.0);
+                button(parent, "Settings", MenuButton::Settings, 220.0);
+                button(parent, "Quit", MenuButton::Quit, 220.0);
+            });
+            parent
+                .spawn(page(MenuPage::Settings))
+                .with_children(|parent| {
+                    setting(
+                        parent,
+                        SettingText::Fov,
+                        MenuButton::Fov(-FOV_STEP),
+                        MenuButton::Fov(FOV_STEP),
+                    );
+                    setting(
+                        parent,
+                        SettingText::Sensitivity,
+                        MenuButton::Sensitivity(SENSITIVITY_STEP.recip()),
+                        MenuButton::Sensitivity(SENSITIVITY_STEP),
+                    );
+                    setting(
+                        parent,
+                        SettingText::RenderDistance,
+                        MenuButton::RenderDistance(-1),
+                        MenuButton::RenderDistance(1),
+                    );
+                    button(parent, "Controls", MenuButton::Controls, 220.0);
+                    button(parent, "Back", MenuButton::Back, 220.0);
+                });
+            parent
+                .spawn(page(MenuPage::Controls))
+                .with_children(|parent| {
+                    for action in Action::ALL {
+                        control(parent, action);
+                    }
+                    // Back to the settings
+                    button(parent, "Back", MenuButton::Settings, 220.0);
+                });
+        });
+}
+
+pub fn despawn_pause_menu(mut commands: Commands, menu: Query<Entity, With<PauseMenu>>) {
+    for entity in &menu {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn press_menu_buttons(
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut pages: Query<(&mut Node, &MenuPage)>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut settings: ResMut<GameSettings>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    type_registry: Res<AppTypeRegistry>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // A click just bound to an action does not also press the button under the cursor
+    if rebinding.is_changed() {
+        return;
+    }
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let mut show_page = |shown: MenuPage| {
+            for (mut node, page) in &mut pages {
+                node.display = match *page == shown {
+                    true => Display::Flex,
+                    false => Display::None,
+                };
+            }
+        };
+        match *button {
+            MenuButton::Resume => next_state.set(GameState::Playing),
+            MenuButton::Settings => show_page(MenuPage::Settings),
+            MenuButton::Controls => show_page(MenuPage::Controls),
+            MenuButton::Back => show_page(MenuPage::Main),
+            MenuButton::Quit => {
+                exit.write(AppExit::Success);
+            }
+            MenuButton::Fov(step) => {
+                settings.fov = (settings.fov + step).clamp(FOV_RANGE.0, FOV_RANGE.1);
+            }
+            MenuButton::Sensitivity(factor) => {
+                bindings.mouse_sensitivity *= factor;
+                bindings.gamepad_sensitivity *= factor;
+                // The sensitivity is stored with the bindings
+                if let Err(error) = bindings.save(BINDINGS_PATH, &type_registry) {
+                    warn!("Could not write {BINDINGS_PATH}: {error}");
+                }
+            }
+            MenuButton::RenderDistance(step) => {
+                settings.render_distance = settings
+                    .render_distance
+                    .saturating_add_signed(step)
+                    .clamp(RENDER_DISTANCE_RANGE.0, RENDER_DISTANCE_RANGE.1);
+            }
+            MenuButton::Rebind(action) => rebinding.0 = Some(action),
+        }
+    }
+}
+
+/// Shows the current values of the settings and the bindings, and highlights the hovered buttons
+pub fn update_menu(
+    settings: Res<GameSettings>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&mut Text, &SettingText)>,
+    mut binding_texts: Query<(&mut Text, &BindingText), Without<SettingText>>,
+    mut buttons: Query<(&Interaction, &mut BackgroundColor), With<MenuButton>>,
+) {
+    // Relative to the default sensitivity, which is easier to read than radians per pixel
+    let sensitivity = bindings.mouse_sensitivity.x / InputBindings::default().mouse_sensitivity.x;
+    for (mut text, setting) in &mut texts {
+        text.0 = match setting {
+            SettingText::Fov => format!("Field of view: {:.0}", settings.fov),
+            SettingText::Sensitivity => format!("Sensitivity: x{sensitivity:.2}"),
+            SettingText::RenderDistance => {
+                format!("Render distance: {} chunks", settings.render_distance)
+            }
+        };
+    }
+    for (mut text, BindingText(action)) in &mut binding_texts {
+        text.0 = match rebinding.0 == Some(*action) {
+            true => "Press an input...".to_string(),
+            false => bindings
+                .bindings(*action)
+                .map(|binding| match binding {
+                    Binding::Key(key) => format!("{key:?}"),
+                    Binding::Mouse(button) => format!("Mouse {button:?}"),
+                    Binding::Gamepad(button) => format!("Gamepad {button:?}"),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+    }
+    for (interaction, mut color) in &mut buttons {
+        color.0 = match interaction {
+            Interaction::Pressed => Color::srgb(0.15, 0.15, 0.15),
+            Interaction::Hovered => Color::srgb(0.35, 0.35, 0.35),
+            Interaction::None => Color::srgb(0.25, 0.25, 0.25),
+        };
+    }
+}
+
+/// Gives the settings to the camera
+pub fn apply_settings(settings: Res<GameSettings>, mut cameras: Query<&mut Projection>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for mut projection in &mut cameras {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = settings.fov.to_radians();
+            perspective.far = (settings.render_distance as usize * CHUNK_SIZE) as f32;
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::ImageSampler,
+    input::mouse::AccumulatedMouseScroll,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    window::PrimaryWindow,
+};
+
+use super::player::Player;
+use super::world::{chunk_coord, ChunkRemeshed, MapMode, VxWorld};
+use super::{CHUNK_SIZE, WORLD_D, WORLD_W};
+
+/// Side of the minimap, in pixels
+const MINIMAP_SIZE: f32 = 192.0;
+/// Number of chunk columns drawn into the map each frame, so the first drawing of the whole world
+/// does not freeze the game
+const COLUMNS_PER_FRAME: usize = 16;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 8.0;
+const MARKER_SIZE: u32 = 15;
+const TOGGLE_KEY: KeyCode = KeyCode::KeyM;
+
+/// The map of the whole world seen from above, one pixel per column, and how it is displayed
+#[derive(Resource)]
+pub struct MapState {
+    pub image: Handle<Image>,
+    /// Chunk columns (X, Z) waiting to be drawn into the image
+    pending_columns: HashSet<(usize, usize)>,
+    /// The pixels of the image, drawn a few columns at a time and copied into the image once no
+    /// column is pending, so it is sent to the GPU once for each batch
+    pixels: Vec<u8>,
+    /// Size of a column on the minimap, in pixels
+    pub minimap_zoom: f32,
+    /// Size of a column on the full screen map, in pixels
+    pub full_screen_zoom: f32,
+    pub full_screen: bool,
+}
+
+/// A node showing the map: the minimap in the corner of the screen, or the full screen map
+#[derive(Debug, Component)]
+pub struct MapView {
+    pub full_screen: bool,
+}
+
+/// The map image inside a [MapView], moved so the player stays at the centre
+#[derive(Debug, Component)]
+pub struct MapImage;
+
+/// The arrow showing the position of the player and where they look
+#[derive(Debug, Component)]
+pub struct PlayerMarker;
+
+// An arrow pointing up, to north
+fn marker_image() -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: MARKER_SIZE,
+            height: MARKER_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    let center = (MARKER_SIZE / 2) as i32;
+    for y in 0..MARKER_SIZE {
+        for x in 0..MARKER_SIZE {
+            let half_width = y as i32 / 2;
+            let color = match (x as i32 - center).abs() {
+                distance if distance < half_width => [230, 40, 40, 255],
+                distance if distance == half_width => [255, 255, 255, 255],
+                _ => continue,
+            };
+            let offset = 4 * (x + y * MARKER_SIZE) as usize;
+            if let Some(data) = image.data.as_mut() {
+                data[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+    }
+    image
+}
+
+fn map_nodes(parent: &mut ChildSpawnerCommands, image: Handle<Image>, marker: Handle<Image>) {
+    parent.spawn((
+        MapImage,
+        ImageNode::new(image),
+        Node {
+            position_type: PositionType::Absolute,
+            ..default()
+        },
+    ));
+    parent.spawn((
+        PlayerMarker,
+        ImageNode::new(marker),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(50.0),
+            top: Val::Percent(50.0),
+            width: Val::Px(MARKER_SIZE as f32),
+            height: Val::Px(MARKER_SIZE as f32),
+            margin: UiRect::all(Val::Px(-(MARKER_SIZE as f32) / 2.0)),
+            ..default()
+        },
+    ));
+}
+
+/// Creates the map image, waiting to be drawn, and the minimap in the top right corner of the
+/// screen, along with the hidden full screen map
+pub fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut map = Image::new_fill(
+        Extent3d {
+            width: (WORLD_W * CHUNK_SIZE) as u32,
+            height: (WORLD_D * CHUNK_SIZE) as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    map.sampler = ImageSampler::nearest();
+    let pixels = map.data.clone().unwrap_or_default();
+    let image = images.add(map);
+    let marker = images.add(marker_image());
+
+    commands
+        .spawn((
+            MapView { full_screen: false },
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                width: Val::Px(MINIMAP_SIZE),
+                height: Val::Px(MINIMAP_SIZE),
+                border: UiRect::all(Val::Px(2.0)),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK),
+            BorderColor(Color::WHITE),
+        ))
+        .with_children(|parent| map_nodes(parent, image.clone(), marker.clone()));
+    commands
+        .spawn((
+            MapView { full_screen: true },
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                overflow: Overflow::clip(),
+                display: Display::None,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.85)),
+        ))
+        .with_children(|parent| map_nodes(parent, image.clone(), marker));
+
+    commands.insert_resource(MapState {
+        image,
+        pending_columns: (0..WORLD_W)
+            .flat_map(|x| (0..WORLD_D).map(move |z| (x, z)))
+            .collect(),
+        pixels,
+        minimap_zoom: 2.0,
+        full_screen_zoom: 1.0,
+        full_screen: false,
+    });
+}
+
+/// Draws again the chunk columns whose meshes changed, a few of them each frame, and updates the
+/// image once they are all drawn
+pub fn update_map_image(
+    mut state: ResMut<MapState>,
+    mut remeshed: EventReader<ChunkRemeshed>,
+    my_world: Option<Res<VxWorld>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for ChunkRemeshed(chunk_id) in remeshed.read() {
+        let (x, _, z) = chunk_coord(*chunk_id);
+        state.pending_columns.insert((x, z));
+        // The shading of the first row of the column to the south depends on this one
+        if z + 1 < WORLD_D {
+            state.pending_columns.insert((x, z + 1));
+        }
+    }
+    let Some(my_world) = my_world else {
+        return;
+    };
+    if state.pending_columns.is_empty() {
+        return;
+    }
+    let columns: Vec<(usize, usize)> = state
+        .pending_columns
+        .iter()
+        .take(COLUMNS_PER_FRAME)
+        .copied()
+        .collect();
+    let row_length = WORLD_W * CHUNK_SIZE;
+    for (x, z) in columns {
+        state.pending_columns.remove(&(x, z));
+        let corner = IVec2::new(x as i32, z as i32) * CHUNK_SIZE as i32;
+        let pixels = my_world.render_map(
+            corner,
+            corner + IVec2::splat(CHUNK_SIZE as i32 - 1),
+            &MapMode::Surface,
+        );
+        for (row, line) in pixels.rows().enumerate() {
+            let start = 4 * ((z * CHUNK_SIZE + row) * row_length + x * CHUNK_SIZE);
+            for (pixel, destination) in line.zip(state.pixels[start..].chunks_exact_mut(4)) {
+                destination.copy_from_slice(&pixel.0);
+            }
+        }
+    }
+    if !state.pending_columns.is_empty() {
+        return;
+    }
+    // Getting the image mutably sends it to the GPU again
+    if let Some(data) = images
+        .get_mut(&state.image)
+        .and_then(|image| image.data.as_mut())
+    {
+        data.copy_from_slice(&state.pixels);
+    }
+}
+
+/// Opens or closes the full screen map
+pub fn toggle_map(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut state: ResMut<MapState>,
+    mut views: Query<(&mut Node, &MapView)>,
+) {
+    if !keyboard_input.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    state.full_screen = !state.full_screen;
+    for (mut node, _) in views.iter_mut().filter(|(_, view)| view.full_screen) {
+        node.display = match state.full_screen {
+            true => Display::Flex,
+            false => Display::None,
+        };
+    }
+}
+
+/// Zooms the visible map in and out, with the mouse wheel or the + and - keys
+pub fn zoom_map(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    accumulated_mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut state: ResMut<MapState>,
+) {
+    let mut steps = 0.0;
+    if keyboard_input.just_pressed(KeyCode::Equal)
+        || keyboard_input.just_pressed(KeyCode::NumpadAdd)
+    {
+        steps += 1.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::Minus)
+        || keyboard_input.just_pressed(KeyCode::NumpadSubtract)
+    {
+        steps -= 1.0;
+    }
+    // The wheel is left to the game while the map is closed
+    if state.full_screen {
+        steps += accumulated_mouse_scroll.delta.y.signum();
+    }
+    if steps == 0.0 {
+        return;
+    }
+    let zoom = match state.full_screen {
+        true => &mut state.full_screen_zoom,
+        false => &mut state.minimap_zoom,
+    };
+    *zoom = (*zoom * 2_f32.powf(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+}
+
+/// Keeps the player at the centre of the maps, the marker turning with them
+pub fn follow_player(
+    state: Res<MapState>,
+    player: Query<&Transform, (With<Player>, Without<PlayerMarker>)>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    views: Query<(&Children, &MapView)>,
+    mut map_images: Query<&mut Node, With<MapImage>>,
+    mut markers: Query<&mut Transform, With<PlayerMarker>>,
+) {
+    let Ok(player) = player.single() else {
+        return;
+    };
+    // A voxel spans half a block around its position, the pixel of a column starts half a block
+    // before it
+    let position = player.translation.xz() + Vec2::splat(0.5);
+    let (yaw, _, _) = player.rotation.to_euler(EulerRot::YXZ);
+    let window_size = window.single().map_or(Vec2::ZERO, Window::size);
+
+    for (children, view) in &views {
+        let (size, zoom) = match view.full_screen {
+            true => (window_size, state.full_screen_zoom),
+            // Inside the borders
+            false => (Vec2::splat(MINIMAP_SIZE - 4.0), state.minimap_zoom),
+        };
+        for child in children.iter() {
+            if let Ok(mut node) = map_images.get_mut(child) {
+                let corner = size / 2.0 - position * zoom;
+                node.left = Val::Px(corner.x);
+                node.top = Val::Px(corner.y);
+                node.width = Val::Px((WORLD_W * CHUNK_SIZE) as f32 * zoom);
+                node.height = Val::Px((WORLD_D * CHUNK_SIZE) as f32 * zoom);
+            }
+            // Turning left spins the arrow counterclockwise, the Y axis of the UI pointing down
+            if let Ok(mut transform) = markers.get_mut(child) {
+                transform.rotation = Quat::from_rotation_z(-yaw);
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Small pieces of blocks flying off when blocks are broken, placed or blown up. The particles are
+//! quads cut out of the tile of their block, always facing the camera, falling and bouncing on the
+//! voxel grid. A fixed number of them is spawned once and reused, the oldest particle being taken
+//! back when they are all in use, so a mass destruction does not spawn anything.
+
+use std::collections::HashMap;
+
+use bevy::{
+    image::{ImageLoaderSettings, ImageSampler},
+    prelude::*,
+    render::mesh::VertexAttributeValues,
+};
+
+use super::player::{BlockBroken, BlockPlaced, WorldModelCamera};
+use super::world::{ChunkRng, Exploded, Voxel, VxWorld};
+use super::WORLD_SEED;
+
+/// The particles spawned once and for all
+const MAX_PARTICLES: usize = 1024;
+/// Width of a particle, in blocks
+const PARTICLE_SIZE: f32 = 0.15;
+/// Pieces a tile is cut into along each side, a particle showing one of them
+const PIECES: u32 = 4;
+/// Seconds a particle lives, at least and at most
+const LIFETIME: (f32, f32) = (0.5, 1.2);
+// In blocks per second squared
+const GRAVITY: f32 = 20.0;
+/// Part of its speed a particle keeps when bouncing on a block
+const BOUNCE: f32 = 0.3;
+/// Particles of a broken block, a placed block, and of each block destroyed by an explosion
+const BREAK_PARTICLES: u32 = 24;
+const PLACE_PARTICLES: u32 = 8;
+const EXPLOSION_PARTICLES: u32 = 2;
+/// Speed, in blocks per second, the particles of an explosion are thrown away from its center at
+const EXPLOSION_SPEED: f32 = 8.0;
+
+/// A particle, hidden once its life ran out
+#[derive(Debug, Default, Component)]
+pub struct Particle {
+    velocity: Vec3,
+    /// Seconds left to live
+    life: f32,
+}
+
+/// The particles, and the quads they show
+#[derive(Debug, Resource)]
+pub struct ParticlePool {
+    // By tile row, tile column and piece of the tile
+    quads: HashMap<(u32, u32, u32), Handle<Mesh>>,
+    entities: Vec<Entity>,
+    // The next particle to emit, which is the oldest one
+    next: usize,
+    rng: ChunkRng,
+}
+
+impl ParticlePool {
+    // A random number in [-1, 1]
+    fn random(&mut self) -> f32 {
+        self.rng.range(-1000, 1001) as f32 / 1000.0
+    }
+
+    // A quad showing a piece of a tile, built the first time it is asked for
+    fn quad(
+        &mut self,
+        (row, column): (u32, u32),
+        piece: u32,
+        meshes: &mut Assets<Mesh>,
+    ) -> Handle<Mesh> {
+        self.quads
+            .entry((row, column, piece))
+            .or_insert_with(|| {
+                let mut mesh = Mesh::from(Rectangle::from_length(PARTICLE_SIZE));
+                if let Some(VertexAttributeValues::Float32x2(uvs)) =
+                    mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0)
+                {
+                    let (x, y) = ((piece % PIECES) as f32, (piece / PIECES) as f32);
+                    for uv in uvs.iter_mut() {
+                        *uv = [
+                            (column as f32 + (x + uv[0]) / PIECES as f32) / 32.0,
+                            (row as f32 + (y + uv[1]) / PIECES as f32) / 32.0,
+                        ];
+                    }
+                }
+                meshes.add(mesh)
+            })
+            .clone()
+    }
+}
+
+pub fn spawn_particle_pool(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color_texture: Some(asset_server.load_with_settings(
+            "textures.png",
+            |settings: &mut ImageLoaderSettings| {
+                settings.sampler = ImageSampler::nearest();
+            },
+        )),
+        // The leaves and the flowers have holes
+        alpha_mode: AlphaMode::Mask(0.5),
+        unlit: true,
+        cull_mode: None,
+        ..default()
+    });
+    let entities = (0..MAX_PARTICLES)
+        .map(|_| {
+            commands
+                .spawn((
+                    Particle::default(),
+                    Mesh3d::default(),
+                    MeshMaterial3d(material.clone()),
+                    Transform::default(),
+                    Visibility::Hidden,
+                ))
+                .id()
+        })
+        .collect();
+    commands.insert_resource(ParticlePool {
+        quads: HashMap::new(),
+        entities,
+        next: 0,
+        rng: ChunkRng::new(WORLD_SEED, 0, 0),
+    });
+}
+
+// Particles thrown out of a voxel
+struct Burst {
+    position: IVec3,
+    voxel: Voxel,
+    count: u32,
+    // How far from the center of the voxel they start, in blocks
+    spread: f32,
+    // Whether they start on the sides of the voxel rather than inside of it
+    outside: bool,
+    // Speed added to the random one
+    push: Vec3,
+}
+
+/// Throws the particles of the blocks broken, placed and blown up
+pub fn emit_particles(
+    mut broken: EventReader<BlockBroken>,
+    mut placed: EventReader<BlockPlaced>,
+    mut exploded: EventReader<Exploded>,
+    mut pool: ResMut<ParticlePool>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Mesh3d, &mut Visibility)>,
+) {
+    let breaks = broken.read().map(|event| Burst {
+        position: event.position,
+        voxel: event.voxel,
+        count: BREAK_PARTICLES,
+        spread: 0.4,
+        outside: false,
+        push: Vec3::Y * 2.0,
+    });
+    // The particles of a placed block come out of its sides
+    let places = placed.read().map(|event| Burst {
+        position: event.position,
+        voxel: event.voxel,
+        count: PLACE_PARTICLES,
+        spread: 0.55,
+        outside: true,
+        push: Vec3::ZERO,
+    });
+    let explosions = exploded.read().flat_map(|event| {
+        event.destroyed.iter().map(|(position, voxel)| Burst {
+            position: *position,
+            voxel: *voxel,
+            count: EXPLOSION_PARTICLES,
+            spread: 0.4,
+            outside: false,
+            push: (position.as_vec3() - event.center).normalize_or_zero() * EXPLOSION_SPEED,
+        })
+    });
+    for burst in breaks.chain(places).chain(explosions) {
+        let Some(tile) = burst.voxel.side_tile() else {
+            continue;
+        };
+        for _ in 0..burst.count {
+            let entity = pool.entities[pool.next];
+            pool.next = (pool.next + 1) % pool.entities.len();
+            let mut offset = Vec3::new(pool.random(), pool.random(), pool.random());
+            if burst.outside {
+                // Pushed onto the faces of the cube
+                offset /= offset.abs().max_element().max(f32::EPSILON);
+            }
+            let piece = pool.rng.range(0, (PIECES * PIECES) as i32) as u32;
+            let life = LIFETIME.0 + (LIFETIME.1 - LIFETIME.0) * (pool.random() + 1.0) / 2.0;
+            let quad = pool.quad(tile, piece, &mut meshes);
+            let Ok((mut particle, mut transform, mut mesh, mut visibility)) =
+                particles.get_mut(entity)
+            else {
+                continue;
+            };
+            *particle = Particle {
+                velocity: offset * 2.0 + burst.push,
+                life,
+            };
+            transform.translation = burst.position.as_vec3() + offset * burst.spread;
+            mesh.0 = quad;
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+/// Moves the particles, stopping them against the solid blocks, and turns them toward the camera
+pub fn update_particles(
+    time: Res<Time>,
+    my_world: Option<Res<VxWorld>>,
+    camera: Query<&GlobalTransform, With<WorldModelCamera>>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+) {
+    let delta = time.delta_secs();
+    let rotation = camera
+        .single()
+        .map(|camera| camera.rotation())
+        .unwrap_or_default();
+    let is_solid = |point: Vec3| {
+        my_world.as_ref().is_some_and(|my_world| {
+            my_world
+                .get_voxel(point.round().as_ivec3())
+                .cube_type
+                .is_solid()
+        })
+    };
+    for (mut particle, mut transform, mut visibility) in &mut particles {
+        if particle.life <= 0.0 {
+            continue;
+        }
+        particle.life -= delta;
+        if particle.life <= 0.0 {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        particle.velocity.y -= GRAVITY * delta;
+        // One axis at a time, so the particles slide along the blocks they hit
+        for axis in 0..3 {
+            let mut next = transform.translation;
+            next[axis] += particle.velocity[axis] * delta;
+            if is_solid(next) {
+                particle.velocity[axis] *= -BOUNCE;
+            } else {
+                transform.translation = next;
+            }
+        }
+        transform.rotation = rotation;
+    }
+}
@@ -1,109 +1,643 @@
-use std::f32::consts::FRAC_PI_2;
+use std::{any::TypeId, f32::consts::FRAC_PI_2, fs, path::Path};
 
-use bevy::{input::mouse::AccumulatedMouseMotion, prelude::*};
+use bevy::{
+    prelude::*,
+    reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer},
+    scene::ron,
+};
+use serde::de::DeserializeSeed;
 
-use super::PLAYER_POS;
+use super::input::{Action, ActionState};
+use super::inventory::{Inventory, HOTBAR_SIZE, INVENTORY_SIZE, STACK_SIZE};
+use super::sound::Footsteps;
+use super::survival::{Breath, Health};
+use super::target::Target;
+use super::world::{spawn_item_drop, BlockState, CubeMeshes, CubeTypes, Voxel, VxWorld, SAVE_DIR};
+use super::{CHUNK_SIZE, SPAWN_COLUMN, WORLD_H};
+
+/// How far away from the player blocks can be placed or broken
+pub const REACH: f32 = 8.0;
+
+/// Seconds it takes to break a block for each point of its hardness
+const BREAK_TIME_PER_HARDNESS: f32 = 1.5;
+/// Time between two blocks broken at once while the break button is held, in creative mode
+const CREATIVE_BREAK_DELAY: f32 = 0.25;
+
+/// The blocks in the hotbar of a new player
+const STARTING_HOTBAR: [CubeTypes; 9] = [
+    CubeTypes::OakLog,
+    CubeTypes::Dirt,
+    CubeTypes::Stone,
+    CubeTypes::Cobblestone,
+    CubeTypes::MossyCobblestone,
+    CubeTypes::BirchLog,
+    CubeTypes::Leaves,
+    CubeTypes::Furnace,
+    CubeTypes::Chest,
+];
+
+const GAME_MODE_KEY: KeyCode = KeyCode::F4;
+
+/// Height of the eyes of the player above their feet
+pub const EYE_HEIGHT: f32 = 1.6;
+/// How far the head of the player goes above their eyes
+const HEAD_HEIGHT: f32 = 0.2;
+/// Half of the width of the body of the player
+const HALF_WIDTH: f32 = 0.3;
+
+// Speeds and accelerations of the player walking in survival mode, in blocks per second
+const WALK_SPEED: f32 = 4.3;
+const SNEAK_SPEED: f32 = 1.3;
+const JUMP_SPEED: f32 = 8.5;
+const GRAVITY: f32 = 32.0;
+const TERMINAL_SPEED: f32 = 40.0;
+const SWIM_SPEED: f32 = 3.0;
+const SINK_SPEED: f32 = 2.0;
+/// How far from the spawn column a safe place to spawn is looked for, in blocks
+const SPAWN_RADIUS: i32 = 16;
+/// The file the player is saved into, in the save folder
+const PLAYER_FILE: &str = "player.ron";
 
 /// A struct to identify the Player component through queries
 #[derive(Debug, Component)]
 pub struct Player;
 
-/// A struct to identify the Camera sensitivity parameter component through queries
-#[derive(Debug, Component, Deref, DerefMut)]
-pub struct CameraSensitivity(Vec2);
+/// The camera the world is seen through, a child of the player
+#[derive(Debug, Component)]
+pub struct WorldModelCamera;
 
-impl Default for CameraSensitivity {
+/// The body of the player, only seen from the third person cameras
+#[derive(Debug, Component)]
+pub struct PlayerBody;
+
+/// How the player plays: flying with unlimited blocks broken at once, walking and collecting the
+/// blocks they break with their health at stake, or flying through the world without touching it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component)]
+pub enum GameMode {
+    #[default]
+    Creative,
+    Survival,
+    Spectator,
+}
+
+impl GameMode {
+    /// Whether the player targets, breaks and places blocks
+    pub fn can_edit(&self) -> bool {
+        *self != GameMode::Spectator
+    }
+}
+
+/// The vertical movement of the player walking in survival mode
+#[derive(Debug, Default, Component)]
+pub struct Fall {
+    /// The upward speed, in blocks per second
+    pub velocity: f32,
+    pub on_ground: bool,
+    /// The highest the feet went since the player left the ground, to measure the fall
+    pub start: Option<f32>,
+}
+
+/// Sent when the player lands on the ground, with the height they fell from
+#[derive(Debug, Clone, Copy, Event)]
+pub struct Landed {
+    pub distance: f32,
+}
+
+/// Sent when the player breaks a block, with the voxel as it was
+#[derive(Debug, Clone, Copy, Event)]
+pub struct BlockBroken {
+    pub position: IVec3,
+    pub voxel: Voxel,
+}
+
+/// Sent when the player places a block, with the voxel it replaced
+#[derive(Debug, Clone, Copy, Event)]
+pub struct BlockPlaced {
+    pub position: IVec3,
+    pub voxel: Voxel,
+    pub replaced: Voxel,
+}
+
+/// Where the player appears in a new world, their eyes above a solid block
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct SpawnPoint(pub Vec3);
+
+impl Default for SpawnPoint {
     fn default() -> Self {
-        Self(Vec2::new(0.006, 0.004))
+        // Above the whole world, until a block to stand on is found
+        Self(Vec3::new(
+            SPAWN_COLUMN.x as f32,
+            (WORLD_H * CHUNK_SIZE) as f32 + EYE_HEIGHT,
+            SPAWN_COLUMN.y as f32,
+        ))
     }
 }
 
-#[derive(Debug, Component)]
-struct WorldModelCamera;
+/// What is saved of the player along with the world
+#[derive(Debug, Clone, Reflect)]
+pub struct PlayerSave {
+    /// The position of the eyes of the player
+    pub position: Vec3,
+    /// The angles the player looks at, in radians
+    pub yaw: f32,
+    pub pitch: f32,
+    pub game_mode: GameMode,
+    pub inventory: Inventory,
+}
+
+impl PlayerSave {
+    /// Reads the player from a RON file
+    pub fn load(path: impl AsRef<Path>, type_registry: &AppTypeRegistry) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let type_registry = type_registry.read();
+        let registration = type_registry
+            .get(TypeId::of::<Self>())
+            .ok_or("the player save is not registered")?;
+        let mut deserializer =
+            ron::Deserializer::from_str(&text).map_err(|error| error.to_string())?;
+        let reflected = TypedReflectDeserializer::new(registration, &type_registry)
+            .deserialize(&mut deserializer)
+            .map_err(|error| error.to_string())?;
+        let mut save = Self::from_reflect(reflected.as_partial_reflect())
+            .ok_or_else(|| "the player save is incomplete".to_string())?;
+        // A save edited by hand could hold any number of slots
+        save.inventory.slots.resize(INVENTORY_SIZE, None);
+        // and stacks of any size, the empty ones being dropped
+        for slot in &mut save.inventory.slots {
+            slot.take_if(|stack| stack.count == 0 || stack.cube_type == CubeTypes::Empty);
+            if let Some(stack) = slot {
+                stack.count = stack.count.min(STACK_SIZE);
+            }
+        }
+        save.inventory.selected = save.inventory.selected.min(HOTBAR_SIZE - 1);
+        Ok(save)
+    }
+
+    /// Writes the player to a RON file
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        type_registry: &AppTypeRegistry,
+    ) -> Result<(), String> {
+        let type_registry = type_registry.read();
+        let serializer = TypedReflectSerializer::new(self.as_partial_reflect(), &type_registry);
+        let text = ron::ser::to_string_pretty(&serializer, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        fs::write(path, text).map_err(|error| error.to_string())
+    }
+}
+
+/// The block being broken, and for how long the break button has been held on it
+#[derive(Debug, Default, Resource)]
+pub struct BreakProgress {
+    pub position: Option<IVec3>,
+    pub elapsed: f32,
+    /// The time it takes to break the block
+    pub duration: f32,
+    /// Time left before the next block can be broken, while the break button stays held
+    pub cooldown: f32,
+}
+
+impl BreakProgress {
+    /// How far the breaking went, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        match self.duration > 0.0 {
+            true => (self.elapsed / self.duration).min(1.0),
+            false => 1.0,
+        }
+    }
+}
 
 /// Spawning the camera into the scene. Skipping the arm part of the [Bevy first person view
 /// model example](https://bevyengine.org/examples/camera/first-person-view-model/)
-pub fn spawn_view_model(mut commands: Commands) {
+pub fn spawn_view_model(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
     commands
         .spawn((
             Player,
-            CameraSensitivity::default(),
-            Transform::from_translation(PLAYER_POS),
+            GameMode::default(),
+            Fall::default(),
+            Health::default(),
+            Breath::default(),
+            Footsteps::default(),
+            Inventory::with_hotbar(&STARTING_HOTBAR),
+            Transform::from_translation(SpawnPoint::default().0),
             Visibility::default(),
         ))
         .with_children(|parent| {
             parent.spawn((
                 WorldModelCamera,
                 Camera3d::default(),
+                // The sounds of the blocks are heard from the camera
+                SpatialListener::default(),
                 Projection::from(PerspectiveProjection {
                     fov: 90.0_f32.to_radians(),
                     ..default()
                 }),
             ));
+            // The player is as tall as two blocks, their eyes being near the top
+            parent.spawn((
+                PlayerBody,
+                Mesh3d(meshes.add(Capsule3d::new(0.3, 1.2))),
+                MeshMaterial3d(materials.add(Color::srgb(0.2, 0.4, 0.8))),
+                Transform::from_xyz(0.0, -0.7, 0.0),
+                Visibility::Hidden,
+            ));
         });
 }
 
-pub fn rotate_player(
-    accumulated_mouse_motion: Res<AccumulatedMouseMotion>,
-    mut player: Query<(&mut Transform, &CameraSensitivity), With<Player>>,
+/// Finds the spawn point in the generated world, then puts the player back where they were saved,
+/// or at the spawn point in a new world
+pub fn place_player(
+    my_world: Res<VxWorld>,
+    type_registry: Res<AppTypeRegistry>,
+    mut spawn_point: ResMut<SpawnPoint>,
+    mut player: Query<(&mut Transform, &mut GameMode, &mut Inventory), With<Player>>,
+) {
+    if let Some(block) = my_world.safe_spawn(SPAWN_COLUMN, SPAWN_RADIUS) {
+        // The feet of the player rest on the top of the block
+        spawn_point.0 = block.as_vec3() + Vec3::Y * (0.5 + EYE_HEIGHT);
+    } else {
+        warn!("No block to stand on around the spawn column");
+    }
+    let Ok((mut transform, mut game_mode, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    let path = Path::new(SAVE_DIR).join(PLAYER_FILE);
+    if !path.exists() {
+        transform.translation = spawn_point.0;
+        return;
+    }
+    match PlayerSave::load(&path, &type_registry) {
+        Ok(save) => {
+            transform.translation = save.position;
+            transform.rotation = Quat::from_euler(EulerRot::YXZ, save.yaw, save.pitch, 0.0);
+            *game_mode = save.game_mode;
+            *inventory = save.inventory;
+        }
+        Err(error) => {
+            warn!("Could not load {}: {error}", path.display());
+            transform.translation = spawn_point.0;
+        }
+    }
+}
+
+/// Writes the player to the save folder when the game is closed
+pub fn save_player(
+    exit: EventReader<AppExit>,
+    type_registry: Res<AppTypeRegistry>,
+    player: Query<(&Transform, &GameMode, &Inventory), With<Player>>,
 ) {
-    let Ok((mut transform, camera_sensitivity)) = player.single_mut() else {
+    if exit.is_empty() {
+        return;
+    }
+    let Ok((transform, game_mode, inventory)) = player.single() else {
         return;
     };
-    let delta = accumulated_mouse_motion.delta;
-    if delta != Vec2::ZERO {
-        let delta_yaw = -delta.x * camera_sensitivity.x;
-        let delta_pitch = -delta.y * camera_sensitivity.y;
+    let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    let save = PlayerSave {
+        position: transform.translation,
+        yaw,
+        pitch,
+        game_mode: *game_mode,
+        inventory: inventory.clone(),
+    };
+    if let Err(error) = fs::create_dir_all(SAVE_DIR) {
+        warn!("Could not create the save folder: {error}");
+        return;
+    }
+    let path = Path::new(SAVE_DIR).join(PLAYER_FILE);
+    if let Err(error) = save.save(&path, &type_registry) {
+        warn!("Could not save {}: {error}", path.display());
+    }
+}
 
+pub fn rotate_player(actions: Res<ActionState>, mut player: Query<&mut Transform, With<Player>>) {
+    let Ok(mut transform) = player.single_mut() else {
+        return;
+    };
+    if actions.look != Vec2::ZERO {
         let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
 
-        let yaw = yaw + delta_yaw;
+        let yaw = yaw + actions.look.x;
 
         const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
-        let pitch = (pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        let pitch = (pitch + actions.look.y).clamp(-PITCH_LIMIT, PITCH_LIMIT);
 
         transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
     }
 }
 
+/// The positions of the voxels the body of the player overlaps, their eyes being at `eye`
+pub fn body_voxels(eye: Vec3) -> impl Iterator<Item = IVec3> {
+    let min = eye - Vec3::new(HALF_WIDTH, EYE_HEIGHT, HALF_WIDTH);
+    let max = eye + Vec3::new(HALF_WIDTH, HEAD_HEIGHT, HALF_WIDTH);
+    // Voxels span half a block around their position, and merely touching one is no overlap
+    let (min, max) = (
+        (min + 0.5).floor().as_ivec3(),
+        (max - 0.5).ceil().as_ivec3(),
+    );
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+    })
+}
+
+fn collides(my_world: &VxWorld, eye: Vec3) -> bool {
+    body_voxels(eye).any(|position| my_world.get_voxel(position).cube_type.is_solid())
+}
+
+// Walks along the ground, falling, jumping and swimming, without going through solid blocks.
+// Returns the height fallen when the player lands.
+fn walk(
+    transform: &mut Transform,
+    fall: &mut Fall,
+    actions: &ActionState,
+    delta: f32,
+    my_world: &VxWorld,
+) -> Option<f32> {
+    let mut eye = transform.translation;
+    let in_fluid =
+        body_voxels(eye).any(|position| my_world.get_voxel(position).cube_type.is_fluid());
+    let jump = actions.pressed(Action::Jump);
+    if in_fluid {
+        fall.velocity = match jump {
+            true => SWIM_SPEED,
+            false => (fall.velocity - GRAVITY * delta).max(-SINK_SPEED),
+        };
+        // Fluids break the fall
+        fall.start = None;
+    } else {
+        if fall.on_ground && jump {
+            fall.velocity = JUMP_SPEED;
+        }
+        fall.velocity = (fall.velocity - GRAVITY * delta).max(-TERMINAL_SPEED);
+        let feet = eye.y - EYE_HEIGHT;
+        fall.start = Some(fall.start.map_or(feet, |start| start.max(feet)));
+    }
+
+    // The player walks along the direction they face, whatever their pitch
+    let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    let speed = match actions.pressed(Action::Sneak) {
+        true => SNEAK_SPEED,
+        false => WALK_SPEED,
+    };
+    let walked = Quat::from_rotation_y(yaw)
+        * Vec3::new(actions.movement.x, 0.0, -actions.movement.y)
+        * speed
+        * delta;
+    // Each axis moves on its own, so the player slides along the walls
+    for axis in [Vec3::X, Vec3::Z] {
+        let moved = eye + walked * axis;
+        if !collides(my_world, moved) {
+            eye = moved;
+        }
+    }
+
+    let was_on_ground = fall.on_ground;
+    fall.on_ground = false;
+    let mut moved = eye + Vec3::Y * fall.velocity * delta;
+    if collides(my_world, moved) {
+        if fall.velocity < 0.0 {
+            // Rests the feet on the top of the block below
+            let feet = moved.y - EYE_HEIGHT;
+            moved.y = (feet + 0.5).floor() + 0.5 + EYE_HEIGHT;
+            fall.on_ground = true;
+        }
+        if collides(my_world, moved) {
+            moved.y = eye.y;
+        }
+        fall.velocity = 0.0;
+    }
+    transform.translation = moved;
+
+    if fall.on_ground {
+        let start = fall.start.take();
+        if !was_on_ground {
+            return start.map(|start| start - (moved.y - EYE_HEIGHT));
+        }
+    }
+    None
+}
+
+/// Flies in creative and spectator modes, walks in survival mode
 pub fn move_player(
-    input: Res<ButtonInput<KeyCode>>,
-    mut player: Query<&mut Transform, With<Player>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    my_world: Option<Res<VxWorld>>,
+    mut landed: EventWriter<Landed>,
+    mut player: Query<(&mut Transform, &GameMode, &mut Fall), With<Player>>,
 ) {
-    // Here we handle the key pressed by the player, by modifying his position according ro the
-    // direction he's aimed at. To do so, we rotate some vector (found by trial and error) by the
-    // camera rotation and increase or decrease the position accordingly.
-    // NOTE: The *xxx_directions* vectors definitions are scoped to avoid the computation if no key is pressed. It might be
-    // useless.
-
-    let Ok(mut transform) = player.single_mut() else {
+    // The player moves along the direction they look at, the movement of the actions being
+    // rotated by the rotation of the camera
+    let Ok((mut transform, game_mode, mut fall)) = player.single_mut() else {
         return;
     };
+    if let (GameMode::Survival, Some(my_world)) = (game_mode, my_world) {
+        if let Some(distance) = walk(
+            &mut transform,
+            &mut fall,
+            &actions,
+            time.delta_secs(),
+            &my_world,
+        ) {
+            landed.write(Landed { distance });
+        }
+        return;
+    }
+    *fall = Fall::default();
 
-    let mut velocity: Vec3 = Vec3::ZERO;
     let speed = 0.2;
+    let face_direction = transform.rotation.mul_vec3(Vec3::NEG_Z);
+    let straff_direction = transform.rotation.mul_vec3(Vec3::X);
+    let mut velocity =
+        speed * (actions.movement.y * face_direction + actions.movement.x * straff_direction);
 
-    if input.pressed(KeyCode::ArrowDown) || input.pressed(KeyCode::KeyS) {
-        let face_direction = transform.rotation.mul_vec3(Vec3::Z);
-        velocity += speed * face_direction;
+    if actions.pressed(Action::Jump) {
+        velocity += speed * Vec3::Y
     }
-    if input.pressed(KeyCode::ArrowUp) || input.pressed(KeyCode::KeyW) {
-        let face_direction = transform.rotation.mul_vec3(Vec3::Z);
-        velocity -= speed * face_direction;
+    if actions.pressed(Action::Sneak) {
+        velocity -= speed * Vec3::Y
     }
-    if input.pressed(KeyCode::ArrowRight) || input.pressed(KeyCode::KeyD) {
-        let straff_direction = transform.rotation.mul_vec3(Vec3::X);
-        velocity += speed * straff_direction;
+    transform.translation += velocity;
+}
+
+/// F4 switches between the game modes
+pub fn switch_game_mode(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut player: Query<&mut GameMode, With<Player>>,
+) {
+    if !keyboard_input.just_pressed(GAME_MODE_KEY) {
+        return;
     }
-    if input.pressed(KeyCode::ArrowLeft) || input.pressed(KeyCode::KeyA) {
-        let straff_direction = transform.rotation.mul_vec3(Vec3::X);
-        velocity -= speed * straff_direction;
+    for mut game_mode in &mut player {
+        *game_mode = match *game_mode {
+            GameMode::Creative => GameMode::Survival,
+            GameMode::Survival => GameMode::Spectator,
+            GameMode::Spectator => GameMode::Creative,
+        };
+        info!("Game mode: {:?}", *game_mode);
     }
-    if input.pressed(KeyCode::Space) {
-        velocity += speed * Vec3::Y
+}
+
+/// Breaks the targeted block once the break button has been held on it long enough, at once in
+/// creative mode, where holding the button breaks a block every [CREATIVE_BREAK_DELAY]. In
+/// survival mode, the block goes into the inventory, or drops on the ground when
+/// the inventory is full.
+#[allow(clippy::too_many_arguments)]
+pub fn break_block(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    target: Res<Target>,
+    mut progress: ResMut<BreakProgress>,
+    mut player: Query<(&GameMode, &mut Inventory), With<Player>>,
+    mut my_world: ResMut<VxWorld>,
+    mut cube_meshes: ResMut<CubeMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut broken: EventWriter<BlockBroken>,
+) {
+    let Ok((game_mode, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    let Some(position) = target.position().filter(|_| actions.pressed(Action::Break)) else {
+        *progress = BreakProgress::default();
+        return;
+    };
+    if progress.cooldown > 0.0 {
+        progress.cooldown -= time.delta_secs();
+        return;
     }
-    if input.pressed(KeyCode::ShiftLeft) {
-        velocity -= speed * Vec3::Y
+    // Looking at another block starts over
+    if progress.position != Some(position) {
+        *progress = BreakProgress {
+            position: Some(position),
+            elapsed: 0.0,
+            cooldown: 0.0,
+            duration: match game_mode {
+                GameMode::Creative | GameMode::Spectator => 0.0,
+                GameMode::Survival => {
+                    BREAK_TIME_PER_HARDNESS * my_world.get_voxel(position).cube_type.hardness()
+                }
+            },
+        };
+    }
+    progress.elapsed += time.delta_secs();
+    if progress.elapsed >= progress.duration {
+        let voxel = my_world.get_voxel(position);
+        my_world.edit_voxel(position, Voxel::default());
+        broken.write(BlockBroken { position, voxel });
+        // A full inventory leaves the block on the ground, to be picked up later
+        if *game_mode == GameMode::Survival && inventory.add(voxel.cube_type, 1) > 0 {
+            spawn_item_drop(
+                &mut commands,
+                &mut cube_meshes,
+                &mut meshes,
+                voxel.cube_type,
+                position,
+            );
+        }
+        *progress = BreakProgress {
+            cooldown: match game_mode {
+                GameMode::Creative | GameMode::Spectator => CREATIVE_BREAK_DELAY,
+                GameMode::Survival => 0.0,
+            },
+            ..default()
+        };
+    }
+}
+
+/// Places the block of the selected slot against the targeted face. Only creative mode has
+/// unlimited blocks.
+pub fn place_block(
+    actions: Res<ActionState>,
+    mut player: Query<(&Transform, &GameMode, &mut Inventory), With<Player>>,
+    target: Res<Target>,
+    mut my_world: ResMut<VxWorld>,
+    mut placed: EventWriter<BlockPlaced>,
+) {
+    if !actions.just_pressed(Action::Place) {
+        return;
+    }
+    let (Ok((transform, game_mode, mut inventory)), Some(target)) =
+        (player.single_mut(), target.adjacent())
+    else {
+        return;
+    };
+    // Blocks go into empty space, or replace fluids
+    let replaced = my_world.get_voxel(target);
+    if replaced.cube_type != CubeTypes::Empty && !replaced.cube_type.is_fluid() {
+        return;
+    }
+    // but never into the player, who would be stuck in them
+    if body_voxels(transform.translation).any(|position| position == target) {
+        return;
+    }
+    let cube_type = match game_mode {
+        GameMode::Creative => inventory.selected_stack().map(|stack| stack.cube_type),
+        GameMode::Survival => inventory.take_selected(),
+        GameMode::Spectator => None,
+    };
+    if let Some(cube_type) = cube_type {
+        // The state of the new block depends on where the player is looking at
+        let state = BlockState::placed(cube_type.orientation(), *transform.forward());
+        let voxel = Voxel::new(cube_type, state);
+        my_world.edit_voxel(target, voxel);
+        placed.write(BlockPlaced {
+            position: target,
+            voxel,
+            replaced,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::ItemStack;
+
+    #[test]
+    fn saved_stacks_are_kept_within_their_size() {
+        let type_registry = AppTypeRegistry::default();
+        type_registry.write().register::<PlayerSave>();
+        let stack = |cube_type: CubeTypes, count: u32| Some(ItemStack { cube_type, count });
+        let mut inventory = Inventory::default();
+        inventory.slots[0] = stack(CubeTypes::Stone, 0);
+        inventory.slots[1] = stack(CubeTypes::Sand, 1000);
+        inventory.slots[2] = stack(CubeTypes::Empty, 3);
+        inventory.slots[3] = stack(CubeTypes::Dirt, 12);
+        inventory.slots.truncate(4);
+        inventory.selected = 100;
+        let path = std::env::temp_dir().join(format!("player-{}.ron", std::process::id()));
+        PlayerSave {
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            game_mode: GameMode::Survival,
+            inventory,
+        }
+        .save(&path, &type_registry)
+        .unwrap();
+        let loaded = PlayerSave::load(&path, &type_registry);
+        fs::remove_file(&path).unwrap();
+
+        let mut inventory = loaded.unwrap().inventory;
+        assert_eq!(inventory.slots.len(), INVENTORY_SIZE);
+        assert_eq!(
+            inventory.slots[..4],
+            [
+                None,
+                stack(CubeTypes::Sand, STACK_SIZE),
+                None,
+                stack(CubeTypes::Dirt, 12)
+            ]
+        );
+        assert_eq!(inventory.selected, HOTBAR_SIZE - 1);
+        inventory.selected = 0;
+        // Taking from an empty slot gives nothing rather than underflowing
+        assert_eq!(inventory.take_selected(), None);
     }
-    transform.translation += velocity;
 }
@@ -0,0 +1,358 @@
+//! The sounds of the blocks: footsteps, and the blocks broken, placed and landed on, each one
+//! chosen from the material of the block. Gameplay only sends [BlockSoundEvent]s, which are
+//! played by a separate system, so everything but the playback runs without an audio device.
+//! The sounds whose file is missing from the assets are skipped.
+
+use std::collections::HashMap;
+
+use bevy::{
+    asset::io::file::FileAssetReader,
+    audio::{AudioSource, Volume},
+    prelude::*,
+};
+
+use super::player::{BlockBroken, BlockPlaced, Fall, Landed, Player, EYE_HEIGHT};
+use super::world::{CubeTypes, VxWorld};
+
+/// Distance walked on the ground, in blocks, between two footsteps
+const STEP_LENGTH: f32 = 1.7;
+/// Falls shorter than this, in blocks, such as stepping down a block, land silently
+const SILENT_LANDING: f32 = 1.0;
+/// The volume of the footsteps, relatively to the other sounds
+const STEP_VOLUME: f32 = 0.4;
+
+/// What a block sounds like
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockMaterial {
+    Grass,
+    Gravel,
+    Sand,
+    Stone,
+    Wood,
+    Glass,
+    Plant,
+    Fluid,
+}
+
+impl BlockMaterial {
+    fn name(&self) -> &'static str {
+        match self {
+            BlockMaterial::Grass => "grass",
+            BlockMaterial::Gravel => "gravel",
+            BlockMaterial::Sand => "sand",
+            BlockMaterial::Stone => "stone",
+            BlockMaterial::Wood => "wood",
+            BlockMaterial::Glass => "glass",
+            BlockMaterial::Plant => "plant",
+            BlockMaterial::Fluid => "fluid",
+        }
+    }
+}
+
+impl CubeTypes {
+    /// What the cube sounds like, None for the air
+    pub fn material(&self) -> Option<BlockMaterial> {
+        match self {
+            CubeTypes::Empty => None,
+            CubeTypes::Dirt | CubeTypes::BareDirt => Some(BlockMaterial::Grass),
+            CubeTypes::Gravel => Some(BlockMaterial::Gravel),
+            CubeTypes::Sand => Some(BlockMaterial::Sand),
+            CubeTypes::Stone
+            | CubeTypes::Deepslate
+            | CubeTypes::Cobblestone
+            | CubeTypes::MossyCobblestone
+            | CubeTypes::CoalOre
+            | CubeTypes::IronOre
+            | CubeTypes::GoldOre
+            | CubeTypes::DiamondOre
+            | CubeTypes::Furnace => Some(BlockMaterial::Stone),
+            CubeTypes::OakLog
+            | CubeTypes::BirchLog
+            | CubeTypes::SpruceLog
+            | CubeTypes::JungleLog
+            | CubeTypes::Chest
+            | CubeTypes::Sign => Some(BlockMaterial::Wood),
+            CubeTypes::Ice => Some(BlockMaterial::Glass),
+            CubeTypes::Leaves | CubeTypes::Poppy | CubeTypes::Dandelion | CubeTypes::Wheat => {
+                Some(BlockMaterial::Plant)
+            }
+            CubeTypes::Water | CubeTypes::Lava => Some(BlockMaterial::Fluid),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockSound {
+    Step,
+    Break,
+    Place,
+    Land,
+}
+
+impl BlockSound {
+    fn name(&self) -> &'static str {
+        match self {
+            BlockSound::Step => "step",
+            BlockSound::Break => "break",
+            BlockSound::Place => "place",
+            BlockSound::Land => "land",
+        }
+    }
+}
+
+/// A sound to play, made by a block at `position`
+#[derive(Debug, Clone, Copy, PartialEq, Event)]
+pub struct BlockSoundEvent {
+    pub sound: BlockSound,
+    pub material: BlockMaterial,
+    pub position: Vec3,
+}
+
+impl BlockSoundEvent {
+    /// The sound file, such as `sounds/stone/break.ogg`
+    pub fn path(&self) -> String {
+        format!("sounds/{}/{}.ogg", self.material.name(), self.sound.name())
+    }
+}
+
+/// The distance the player walked on the ground since their last footstep
+#[derive(Debug, Default, Component)]
+pub struct Footsteps {
+    walked: f32,
+    // Where the feet were last frame, None while off the ground
+    last: Option<Vec3>,
+}
+
+impl Footsteps {
+    /// Moves the feet to `feet`, and returns whether they took a step. The distance only adds up
+    /// while on the ground, and only horizontally.
+    pub fn walk(&mut self, feet: Vec3, on_ground: bool) -> bool {
+        if !on_ground {
+            self.last = None;
+            return false;
+        }
+        if let Some(last) = self.last.replace(feet) {
+            self.walked += (feet - last).xz().length();
+        }
+        if self.walked >= STEP_LENGTH {
+            self.walked -= STEP_LENGTH;
+            return true;
+        }
+        false
+    }
+}
+
+// The block the feet stand on
+fn block_below(feet: Vec3) -> IVec3 {
+    (feet - Vec3::Y * 0.5).round().as_ivec3()
+}
+
+/// Turns the footsteps, the landings and the blocks broken and placed into block sounds
+pub fn emit_block_sounds(
+    my_world: Option<Res<VxWorld>>,
+    mut player: Query<(&Transform, &Fall, &mut Footsteps), With<Player>>,
+    mut broken: EventReader<BlockBroken>,
+    mut placed: EventReader<BlockPlaced>,
+    mut landed: EventReader<Landed>,
+    mut sounds: EventWriter<BlockSoundEvent>,
+) {
+    let mut send = |sound: BlockSound, cube_type: CubeTypes, position: Vec3| {
+        if let Some(material) = cube_type.material() {
+            sounds.write(BlockSoundEvent {
+                sound,
+                material,
+                position,
+            });
+        }
+    };
+    for event in broken.read() {
+        send(
+            BlockSound::Break,
+            event.voxel.cube_type,
+            event.position.as_vec3(),
+        );
+    }
+    for event in placed.read() {
+        send(
+            BlockSound::Place,
+            event.voxel.cube_type,
+            event.position.as_vec3(),
+        );
+    }
+    let (Some(my_world), Ok((transform, fall, mut footsteps))) = (my_world, player.single_mut())
+    else {
+        landed.clear();
+        return;
+    };
+    let feet = transform.translation - Vec3::Y * EYE_HEIGHT;
+    let ground = my_world.get_voxel(block_below(feet)).cube_type;
+    let fallen = landed
+        .read()
+        .map(|event| event.distance)
+        .fold(0.0, f32::max);
+    if fallen >= SILENT_LANDING {
+        send(BlockSound::Land, ground, feet);
+    }
+    if footsteps.walk(feet, fall.on_ground) {
+        send(BlockSound::Step, ground, feet);
+    }
+}
+
+/// Plays the block sounds where they were made, the footsteps being quieter. Each file is looked
+/// for once, the missing ones being left silent rather than failing to load every time.
+pub fn play_block_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut sounds: EventReader<BlockSoundEvent>,
+    mut files: Local<HashMap<String, Option<Handle<AudioSource>>>>,
+) {
+    for event in sounds.read() {
+        let path = event.path();
+        let Some(source) = files
+            .entry(path)
+            .or_insert_with_key(|path| {
+                let found = FileAssetReader::get_base_path()
+                    .join("assets")
+                    .join(path)
+                    .exists();
+                if !found {
+                    debug!("No sound file at {path}");
+                }
+                found.then(|| asset_server.load(path.clone()))
+            })
+            .clone()
+        else {
+            continue;
+        };
+        let volume = match event.sound {
+            BlockSound::Step => STEP_VOLUME,
+            _ => 1.0,
+        };
+        commands.spawn((
+            AudioPlayer::new(source),
+            PlaybackSettings::DESPAWN
+                .with_spatial(true)
+                .with_volume(Volume::Linear(volume)),
+            Transform::from_translation(event.position),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::{Voxel, VxWorld},
+        CHUNK_VOLUME, WORLD_VOL,
+    };
+
+    // A headless app turning the gameplay events into block sounds, the player standing on the
+    // stone at the origin
+    fn app(on_ground: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<BlockBroken>()
+            .add_event::<BlockPlaced>()
+            .add_event::<Landed>()
+            .add_event::<BlockSoundEvent>()
+            .add_systems(Update, emit_block_sounds);
+        let mut my_world = VxWorld::from_voxels(vec![Voxel::default(); WORLD_VOL * CHUNK_VOLUME]);
+        my_world.set_voxel(IVec3::ZERO, CubeTypes::Stone.into());
+        app.insert_resource(my_world);
+        app.world_mut().spawn((
+            Player,
+            Transform::from_xyz(0.0, 0.5 + EYE_HEIGHT, 0.0),
+            Fall {
+                on_ground,
+                ..default()
+            },
+            Footsteps::default(),
+        ));
+        app
+    }
+
+    fn sounds(app: &mut App) -> Vec<BlockSoundEvent> {
+        app.world_mut()
+            .resource_mut::<Events<BlockSoundEvent>>()
+            .drain()
+            .collect()
+    }
+
+    #[test]
+    fn blocks_broken_and_placed_sound_like_their_material() {
+        let mut app = app(false);
+        app.world_mut().send_event(BlockBroken {
+            position: IVec3::new(3, 1, 0),
+            voxel: CubeTypes::OakLog.into(),
+        });
+        app.world_mut().send_event(BlockPlaced {
+            position: IVec3::new(0, 1, 3),
+            voxel: CubeTypes::Sand.into(),
+            replaced: Voxel::default(),
+        });
+        // The air sounds like nothing
+        app.world_mut().send_event(BlockBroken {
+            position: IVec3::Y,
+            voxel: Voxel::default(),
+        });
+        app.update();
+        assert_eq!(
+            sounds(&mut app),
+            [
+                BlockSoundEvent {
+                    sound: BlockSound::Break,
+                    material: BlockMaterial::Wood,
+                    position: Vec3::new(3.0, 1.0, 0.0),
+                },
+                BlockSoundEvent {
+                    sound: BlockSound::Place,
+                    material: BlockMaterial::Sand,
+                    position: Vec3::new(0.0, 1.0, 3.0),
+                },
+            ]
+        );
+        assert_eq!(
+            BlockSoundEvent {
+                sound: BlockSound::Break,
+                material: BlockMaterial::Wood,
+                position: Vec3::ZERO,
+            }
+            .path(),
+            "sounds/wood/break.ogg"
+        );
+    }
+
+    #[test]
+    fn landings_only_sound_from_high_enough() {
+        let mut app = app(true);
+        app.world_mut().send_event(Landed {
+            distance: SILENT_LANDING - 0.1,
+        });
+        app.update();
+        assert!(sounds(&mut app).is_empty());
+
+        app.world_mut().send_event(Landed {
+            distance: SILENT_LANDING,
+        });
+        app.update();
+        let landings = sounds(&mut app);
+        assert_eq!(landings.len(), 1);
+        assert_eq!(landings[0].sound, BlockSound::Land);
+        // The block below the feet
+        assert_eq!(landings[0].material, BlockMaterial::Stone);
+    }
+
+    #[test]
+    fn a_step_every_step_length() {
+        let mut footsteps = Footsteps::default();
+        let steps = (0..=100)
+            .filter(|step| footsteps.walk(Vec3::X * *step as f32 * 0.1, true))
+            .count();
+        // Ten blocks walked
+        assert_eq!(steps, (10.0 / STEP_LENGTH) as usize);
+        // Only along the ground
+        let mut footsteps = Footsteps::default();
+        assert!(!(0..=100).any(|step| footsteps.walk(Vec3::Y * step as f32, true)));
+        assert!(!(0..=100).any(|step| footsteps.walk(Vec3::X * step as f32, false)));
+    }
+}
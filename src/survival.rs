@@ -0,0 +1,295 @@
+//! The survival layer: the health of the player, lost by falling from too high, by drowning and
+//! by touching hazardous blocks, and the respawn at the spawn point once it runs out. Only the
+//! survival mode runs these systems.
+
+use bevy::prelude::*;
+
+use super::menu::GameState;
+use super::player::{body_voxels, Fall, GameMode, Landed, Player, SpawnPoint};
+use super::world::VxWorld;
+
+/// The health of a new player, in half hearts
+pub const MAX_HEALTH: f32 = 20.0;
+/// Falls up to this height, in blocks, do not hurt. Each block further takes a point of health.
+const SAFE_FALL_HEIGHT: f32 = 3.0;
+/// Seconds the player holds their breath with their head in a fluid
+const MAX_BREATH: f32 = 10.0;
+/// Health lost each second once the breath ran out
+const DROWNING_DAMAGE: f32 = 2.0;
+/// How much faster the breath comes back than it goes
+const BREATH_RECOVERY: f32 = 5.0;
+/// Health gained back each second
+const REGENERATION: f32 = 0.1;
+// Width of the bars above the hotbar, in pixels
+const BAR_WIDTH: f32 = 200.0;
+
+/// The health left to the player, in half hearts
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Health(pub f32);
+
+impl Default for Health {
+    fn default() -> Self {
+        Self(MAX_HEALTH)
+    }
+}
+
+/// Seconds left before the player starts drowning
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Breath(pub f32);
+
+impl Default for Breath {
+    fn default() -> Self {
+        Self(MAX_BREATH)
+    }
+}
+
+/// The root node of the bars above the hotbar
+#[derive(Debug, Component)]
+pub struct SurvivalBars;
+
+/// One of the bars above the hotbar
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum SurvivalBar {
+    Health,
+    Breath,
+}
+
+/// The part of a bar filled following its value
+#[derive(Debug, Component)]
+pub struct BarFill;
+
+/// The root node of the screen shown on death
+#[derive(Debug, Component)]
+pub struct DeathScreen;
+
+#[derive(Debug, Component)]
+pub struct RespawnButton;
+
+/// Run condition of the survival systems
+pub fn in_survival(player: Query<&GameMode, With<Player>>) -> bool {
+    player
+        .single()
+        .is_ok_and(|game_mode| *game_mode == GameMode::Survival)
+}
+
+/// Takes a point of health for each block fallen beyond the safe height
+pub fn take_fall_damage(
+    mut landed: EventReader<Landed>,
+    mut player: Query<&mut Health, With<Player>>,
+) {
+    let Ok(mut health) = player.single_mut() else {
+        return;
+    };
+    for event in landed.read() {
+        let damage = (event.distance - SAFE_FALL_HEIGHT).floor();
+        if damage > 0.0 {
+            health.0 -= damage;
+        }
+    }
+}
+
+/// Uses the breath while the head of the player is in a fluid, then their health
+pub fn drown(
+    time: Res<Time>,
+    my_world: Option<Res<VxWorld>>,
+    mut player: Query<(&Transform, &mut Breath, &mut Health), With<Player>>,
+) {
+    let (Ok((transform, mut breath, mut health)), Some(my_world)) = (player.single_mut(), my_world)
+    else {
+        return;
+    };
+    let delta = time.delta_secs();
+    // The eyes are close enough to the top of the head
+    let head = (transform.translation + 0.5).floor().as_ivec3();
+    if my_world.get_voxel(head).cube_type.is_fluid() {
+        breath.0 -= delta;
+        if breath.0 <= 0.0 {
+            breath.0 = 0.0;
+            health.0 -= DROWNING_DAMAGE * delta;
+        }
+    } else if breath.0 < MAX_BREATH {
+        breath.0 = (breath.0 + BREATH_RECOVERY * delta).min(MAX_BREATH);
+    }
+}
+
+/// Hurts the player for as long as they touch a hazardous block, the worst one counting
+pub fn touch_hazards(
+    time: Res<Time>,
+    my_world: Option<Res<VxWorld>>,
+    mut player: Query<(&Transform, &mut Health), With<Player>>,
+) {
+    let (Ok((transform, mut health)), Some(my_world)) = (player.single_mut(), my_world) else {
+        return;
+    };
+    let damage = body_voxels(transform.translation)
+        .map(|position| my_world.get_voxel(position).cube_type.damage())
+        .fold(0.0, f32::max);
+    if damage > 0.0 {
+        health.0 -= damage * time.delta_secs();
+    }
+}
+
+/// Slowly heals the player while they are not hurt
+pub fn regenerate(time: Res<Time>, mut player: Query<(&mut Health, &Breath), With<Player>>) {
+    let Ok((mut health, breath)) = player.single_mut() else {
+        return;
+    };
+    if health.0 < MAX_HEALTH && breath.0 > 0.0 {
+        health.0 = (health.0 + REGENERATION * time.delta_secs()).min(MAX_HEALTH);
+    }
+}
+
+/// Shows the death screen once the health runs out
+pub fn die(
+    player: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if player.single().is_ok_and(|health| health.0 <= 0.0) {
+        next_state.set(GameState::Dead);
+    }
+}
+
+pub fn spawn_survival_bars(mut commands: Commands) {
+    let bar = |parent: &mut ChildSpawnerCommands, bar: SurvivalBar, color: Color| {
+        parent
+            .spawn((
+                bar,
+                Node {
+                    width: Val::Px(BAR_WIDTH),
+                    height: Val::Px(8.0),
+                    margin: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            ))
+            .with_child((
+                BarFill,
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(color),
+            ));
+    };
+    // Right above the hotbar
+    commands
+        .spawn((
+            SurvivalBars,
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(72.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+        ))
+        .with_children(|parent| {
+            bar(parent, SurvivalBar::Breath, Color::srgb(0.3, 0.6, 1.0));
+            bar(parent, SurvivalBar::Health, Color::srgb(0.8, 0.1, 0.1));
+        });
+}
+
+/// Fills the bars, shown in survival mode only. The breath bar is only shown while it is not
+/// full.
+pub fn update_survival_bars(
+    player: Query<(&GameMode, &Health, &Breath), With<Player>>,
+    mut roots: Query<&mut Visibility, (With<SurvivalBars>, Without<SurvivalBar>)>,
+    mut bars: Query<(&SurvivalBar, &Children, &mut Visibility), Without<SurvivalBars>>,
+    mut fills: Query<&mut Node, With<BarFill>>,
+) {
+    let Ok((game_mode, health, breath)) = player.single() else {
+        return;
+    };
+    for mut visibility in &mut roots {
+        *visibility = match game_mode {
+            GameMode::Survival => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+    for (bar, children, mut visibility) in &mut bars {
+        let (fraction, shown) = match bar {
+            SurvivalBar::Health => (health.0 / MAX_HEALTH, true),
+            SurvivalBar::Breath => (breath.0 / MAX_BREATH, breath.0 < MAX_BREATH),
+        };
+        *visibility = match shown {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+        let mut fills = fills.iter_many_mut(children);
+        while let Some(mut node) = fills.fetch_next() {
+            node.width = Val::Percent(100.0 * fraction.clamp(0.0, 1.0));
+        }
+    }
+}
+
+pub fn spawn_death_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            DeathScreen,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.5, 0.0, 0.0, 0.5)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("You died!"),
+                TextFont::from_font_size(48.0),
+                Node {
+                    margin: UiRect::bottom(Val::Px(24.0)),
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    RespawnButton,
+                    Button,
+                    Node {
+                        width: Val::Px(220.0),
+                        height: Val::Px(40.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                ))
+                .with_child(Text::new("Respawn"));
+        });
+}
+
+pub fn despawn_death_screen(mut commands: Commands, screen: Query<Entity, With<DeathScreen>>) {
+    for entity in &screen {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Brings the player back to the spawn point, healed, when the respawn button is pressed
+pub fn press_respawn_button(
+    buttons: Query<&Interaction, (With<RespawnButton>, Changed<Interaction>)>,
+    spawn_point: Res<SpawnPoint>,
+    mut player: Query<(&mut Transform, &mut Health, &mut Breath, &mut Fall), With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        return;
+    }
+    if let Ok((mut transform, mut health, mut breath, mut fall)) = player.single_mut() {
+        transform.translation = spawn_point.0;
+        *health = Health::default();
+        *breath = Breath::default();
+        *fall = Fall::default();
+    }
+    next_state.set(GameState::Playing);
+}
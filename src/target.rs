@@ -0,0 +1,186 @@
+//! The block the player is aiming at, and the feedback drawn over it: an outline around the block
+//! and cracks spreading on it while it is being broken.
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::ImageSampler,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use super::player::{BreakProgress, GameMode, Player, REACH};
+use super::world::{ChunkRng, FaceType, VxWorld};
+use super::WORLD_SEED;
+
+/// Number of steps the cracks go through before the block breaks
+pub const CRACK_STAGES: usize = 10;
+// Side of the crack texture, the same as the tiles of the atlas
+const CRACK_SIZE: u32 = 16;
+const CRACK_BRANCHES: usize = 6;
+const CRACK_LENGTH: usize = 14;
+
+/// The non empty voxel hit by the ray going out of the camera, and the face the ray enters it from
+#[derive(Debug, Default, Resource)]
+pub struct Target {
+    pub hit: Option<(IVec3, FaceType)>,
+}
+
+impl Target {
+    pub fn position(&self) -> Option<IVec3> {
+        self.hit.as_ref().map(|(position, _)| *position)
+    }
+
+    /// The empty voxel in front of the targeted face, where a block would be placed
+    pub fn adjacent(&self) -> Option<IVec3> {
+        self.hit.as_ref().map(|(position, face)| {
+            let (x, y, z): (i8, i8, i8) = face.clone().into();
+            *position + IVec3::new(x as i32, y as i32, z as i32)
+        })
+    }
+}
+
+/// The cube drawn over the block being broken
+#[derive(Debug, Component)]
+pub struct CrackOverlay;
+
+/// A material for each stage of the cracks
+#[derive(Debug, Resource)]
+pub struct CrackMaterials(Vec<Handle<StandardMaterial>>);
+
+/// Casts a ray from the player through the voxels
+pub fn update_target(
+    player: Query<(&Transform, &GameMode), With<Player>>,
+    my_world: Option<Res<VxWorld>>,
+    mut target: ResMut<Target>,
+) {
+    let (Ok((transform, game_mode)), Some(my_world)) = (player.single(), my_world) else {
+        target.hit = None;
+        return;
+    };
+    // Spectators do not touch the world
+    if !game_mode.can_edit() {
+        target.hit = None;
+        return;
+    }
+    target.hit = my_world.raycast(transform.translation, *transform.forward(), REACH);
+}
+
+/// Outlines the edges of the targeted block
+pub fn draw_target_outline(target: Res<Target>, mut gizmos: Gizmos) {
+    if let Some(position) = target.position() {
+        // Slightly bigger than the block, so the faces do not hide the lines
+        gizmos.cuboid(
+            Transform::from_translation(position.as_vec3()).with_scale(Vec3::splat(1.005)),
+            Color::BLACK,
+        );
+    }
+}
+
+// The textures of the stages: random branches starting from the centre, every pixel of a stage
+// staying cracked in the next ones
+fn crack_images() -> Vec<Image> {
+    let mut rng = ChunkRng::new(WORLD_SEED, 0, 0);
+    let size = CRACK_SIZE as i32;
+    // The rank of each pixel in the order they crack in
+    let mut order = vec![usize::MAX; (CRACK_SIZE * CRACK_SIZE) as usize];
+    let mut cracked = 0;
+    for _ in 0..CRACK_BRANCHES {
+        let direction = IVec2::new(
+            if rng.chance(0.5) { 1 } else { -1 },
+            if rng.chance(0.5) { 1 } else { -1 },
+        );
+        let mut pixel = IVec2::splat(size / 2);
+        for _ in 0..CRACK_LENGTH {
+            let index = (pixel.x + pixel.y * size) as usize;
+            if order[index] == usize::MAX {
+                order[index] = cracked;
+                cracked += 1;
+            }
+            let step = if rng.chance(0.5) {
+                direction
+            } else {
+                IVec2::new(rng.range(-1, 2), rng.range(-1, 2))
+            };
+            pixel = (pixel + step).clamp(IVec2::ZERO, IVec2::splat(size - 1));
+        }
+    }
+    (0..CRACK_STAGES)
+        .map(|stage| {
+            let visible = cracked * (stage + 1) / CRACK_STAGES;
+            let data = order
+                .iter()
+                .flat_map(|rank| match *rank < visible {
+                    true => [20, 20, 20, 200],
+                    false => [0, 0, 0, 0],
+                })
+                .collect();
+            let mut image = Image::new(
+                Extent3d {
+                    width: CRACK_SIZE,
+                    height: CRACK_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+            );
+            image.sampler = ImageSampler::nearest();
+            image
+        })
+        .collect()
+}
+
+/// Creates the materials of the crack stages, and the hidden cube showing them
+pub fn spawn_crack_overlay(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let stages: Vec<Handle<StandardMaterial>> = crack_images()
+        .into_iter()
+        .map(|image| {
+            materials.add(StandardMaterial {
+                base_color_texture: Some(images.add(image)),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })
+        })
+        .collect();
+    commands.spawn((
+        CrackOverlay,
+        Mesh3d(meshes.add(Cuboid::from_length(1.002))),
+        MeshMaterial3d(stages[0].clone()),
+        Transform::default(),
+        Visibility::Hidden,
+    ));
+    commands.insert_resource(CrackMaterials(stages));
+}
+
+/// Moves the cracks onto the block being broken, and shows the stage its breaking reached
+pub fn update_crack_overlay(
+    progress: Res<BreakProgress>,
+    stages: Res<CrackMaterials>,
+    mut overlay: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut MeshMaterial3d<StandardMaterial>,
+        ),
+        With<CrackOverlay>,
+    >,
+) {
+    let Ok((mut transform, mut visibility, mut material)) = overlay.single_mut() else {
+        return;
+    };
+    let Some(position) = progress.position else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    let stage = (progress.fraction() * CRACK_STAGES as f32) as usize;
+    transform.translation = position.as_vec3();
+    material.0 = stages.0[stage.min(CRACK_STAGES - 1)].clone();
+    *visibility = Visibility::Visible;
+}
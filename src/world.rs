@@ -1,17 +1,61 @@
 use core::f32;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use bevy::image::{ImageLoaderSettings, ImageSampler};
 use bevy::prelude::*;
 use bevy::reflect::TypePath;
 use bevy::render::mesh::MeshVertexAttribute;
 use bevy::render::render_resource::{AsBindGroup, ShaderRef, VertexFormat};
-use chunk::{CubeTypes, VxWorldCoord};
-use noisy_bevy::simplex_noise_2d;
 
+pub use anvil::{AnvilChunk, AnvilRegion, AnvilSection};
+pub use block_entity::{
+    index_block_entities, sync_block_entities, BlockEntities, BlockEntity, BlockEntityData,
+    ChestContents, SignText,
+};
+pub use block_names::{block_state_name, BlockNames};
+pub use block_state::{BlockState, Mirror};
+pub use chunk::{CubeTypes, FaceType, Voxel, VxWorldCoord};
+pub use decoration::ChunkRng;
+pub use explosion::{explode, Exploded, Explosion};
+pub use falling::{
+    fall, init_cube_meshes, spawn_item_drop, spin_item_drops, start_falling, CubeMeshes,
+    FallingBlock, ItemDrop,
+};
+pub use heightmap::{EdgeMode, Heightmap, SplatMap, TerrainGenerator};
+pub use map::{column_color, column_height, render_map, MapMode};
+pub use mesh_export::{ExportMesh, ATLAS_PATH};
+pub use save::{load_block_entities, save_world, SAVE_DIR};
+pub use schematic::{Schematic, SchematicVersion};
+pub use strata::{
+    count_ores, ore_counts_per_chunk, LayerBottom, OreVein, StrataLayer, UndergroundConfig,
+};
+pub use tick::{run_block_ticks, BlockTicks, TickHandler, TICK_RATE};
+pub use vox::{VoxInstance, VoxModel, VoxPaletteMapping, VoxScene};
+
+mod anvil;
+mod block_entity;
+mod block_names;
+mod block_state;
 mod chunk;
+mod decoration;
+mod explosion;
+mod falling;
+mod heightmap;
+mod map;
+mod mesh_export;
+pub mod nbt;
+mod save;
+mod schematic;
+mod strata;
+mod tick;
+mod vox;
 
 use super::{
-    CHUNK_AREA, CHUNK_SIZE, CHUNK_VOLUME, WORLD_AREA, WORLD_D, WORLD_H, WORLD_VOL, WORLD_W,
+    CHUNK_AREA, CHUNK_SIZE, CHUNK_VOLUME, WORLD_AREA, WORLD_D, WORLD_H, WORLD_SEED, WORLD_VOL,
+    WORLD_W,
 };
 
 const ATTRIBUTE_VX_TYPE: MeshVertexAttribute =
@@ -20,12 +64,29 @@ const ATTRIBUTE_VX_AO: MeshVertexAttribute =
     MeshVertexAttribute::new("VxAo", 10001, VertexFormat::Uint32);
 
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+#[bind_group_data(ChunkMaterialKey)]
 pub struct ChunkMaterial {
     #[uniform(0)]
     color: LinearRgba,
     #[texture(1)]
     #[sampler(2)]
     color_texture: Option<Handle<Image>>,
+    /// Draws the edges of the triangles only. Needs the `POLYGON_MODE_LINE` feature of the GPU.
+    pub wireframe: bool,
+}
+
+/// What the pipelines of the chunk materials differ by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkMaterialKey {
+    wireframe: bool,
+}
+
+impl From<&ChunkMaterial> for ChunkMaterialKey {
+    fn from(material: &ChunkMaterial) -> Self {
+        Self {
+            wireframe: material.wireframe,
+        }
+    }
 }
 
 impl Material for ChunkMaterial {
@@ -41,7 +102,7 @@ impl Material for ChunkMaterial {
         _pipeline: &bevy::pbr::MaterialPipeline<Self>,
         descriptor: &mut bevy::render::render_resource::RenderPipelineDescriptor,
         layout: &bevy::render::mesh::MeshVertexBufferLayoutRef,
-        _key: bevy::pbr::MaterialPipelineKey<Self>,
+        key: bevy::pbr::MaterialPipelineKey<Self>,
     ) -> Result<(), bevy::render::render_resource::SpecializedMeshPipelineError> {
         let vertex_layout = layout.0.get_layout(&[
             Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
@@ -50,53 +111,369 @@ impl Material for ChunkMaterial {
             ATTRIBUTE_VX_AO.at_shader_location(3),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
+        if key.bind_group_data.wireframe {
+            descriptor.primitive.polygon_mode = bevy::render::render_resource::PolygonMode::Line;
+        }
         Ok(())
     }
 }
 
-struct VxWorld {
-    chunks: Vec<chunk::VxChunkMesh>,
-    _voxels: Vec<chunk::CubeTypes>,
+/// A voxel replaced through [VxWorld::edit_voxel] or [VxWorld::edit_voxels], from `before` to
+/// `after`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelEdit {
+    pub position: IVec3,
+    pub before: Voxel,
+    pub after: Voxel,
+}
+
+/// The voxels of the whole world, along with the meshes of the chunks displaying them
+#[derive(Resource)]
+pub struct VxWorld {
+    voxels: Vec<Voxel>,
+    chunk_meshes: Vec<Handle<Mesh>>,
+    // Chunks to mesh again
+    dirty_chunks: HashSet<usize>,
+    // Chunks differing from the generated ones, to write on the disk
+    edited_chunks: HashSet<usize>,
+    // Positions of the voxels edited since the block entities were last synchronized
+    edits: Vec<IVec3>,
+    // Positions where a block may have lost the block holding it up since the last check
+    unsettled: Vec<IVec3>,
+    // Voxels replaced on behalf of the player since the edit history last took them
+    voxel_edits: Vec<VoxelEdit>,
+    generation_time: Duration,
+    // Time spent meshing the last batch of chunks, and their number
+    meshing_time: Duration,
+    meshed_chunks: usize,
 }
 
 impl VxWorld {
-    fn new() -> Self {
-        let _voxels = map_generation();
-        let chunks = (0..WORLD_VOL)
-            .into_iter()
-            .map(|i| {
-                chunk::VxChunkMesh::new(
-                    (
-                        (i % WORLD_W),
-                        (i / WORLD_AREA),
-                        ((i % WORLD_AREA) / WORLD_W),
-                    ),
-                    &_voxels,
-                )
+    fn new(underground: &UndergroundConfig, terrain: &TerrainGenerator) -> Self {
+        let start = Instant::now();
+        let mut voxels = map_generation(underground, terrain);
+        let generation_time = start.elapsed();
+        save::load_chunks(&mut voxels);
+        Self {
+            generation_time,
+            ..Self::from_voxels(voxels)
+        }
+    }
+
+    /// A world made of the given voxels, laid out chunk after chunk, without any mesh. Nothing
+    /// is read from the disk, so it can be edited headless.
+    pub fn from_voxels(voxels: Vec<Voxel>) -> Self {
+        assert_eq!(voxels.len(), WORLD_VOL * CHUNK_VOLUME);
+        Self {
+            voxels,
+            chunk_meshes: Vec::new(),
+            dirty_chunks: HashSet::new(),
+            edited_chunks: HashSet::new(),
+            edits: Vec::new(),
+            unsettled: Vec::new(),
+            voxel_edits: Vec::new(),
+            generation_time: Duration::ZERO,
+            meshing_time: Duration::ZERO,
+            meshed_chunks: 0,
+        }
+    }
+
+    /// The meshes of the chunks, indexed by their ID
+    pub fn chunk_meshes(&self) -> &[Handle<Mesh>] {
+        &self.chunk_meshes
+    }
+
+    /// How long the generation of the voxels took
+    pub fn generation_time(&self) -> Duration {
+        self.generation_time
+    }
+
+    /// How long the last batch of chunks took to mesh, along with the number of chunks
+    pub fn meshing_time(&self) -> (Duration, usize) {
+        (self.meshing_time, self.meshed_chunks)
+    }
+
+    /// The voxels of a single chunk, stored one after the other
+    pub fn chunk_voxels(&self, chunk_id: usize) -> &[Voxel] {
+        &self.voxels[chunk_id * CHUNK_VOLUME..(chunk_id + 1) * CHUNK_VOLUME]
+    }
+
+    /// Counts the voxels of each configured ore inside of a chunk
+    pub fn ore_counts(
+        &self,
+        chunk_id: usize,
+        underground: &UndergroundConfig,
+    ) -> HashMap<CubeTypes, usize> {
+        count_ores(self.chunk_voxels(chunk_id), underground)
+    }
+
+    /// Returns the positions of the voxels edited since the last call
+    pub fn take_edits(&mut self) -> Vec<IVec3> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Returns the positions where a block may have lost the block holding it up since the last
+    /// call: above the edited voxels, and the edited voxels themselves
+    pub fn take_unsettled(&mut self) -> Vec<IVec3> {
+        std::mem::take(&mut self.unsettled)
+    }
+
+    /// Returns the voxels replaced through [VxWorld::edit_voxel] and [VxWorld::edit_voxels] since
+    /// the last call, in the order they were replaced
+    pub fn take_voxel_edits(&mut self) -> Vec<VoxelEdit> {
+        std::mem::take(&mut self.voxel_edits)
+    }
+
+    /// The highest solid block with two free blocks above it, a player being able to stand there.
+    /// The columns around `column` are searched from the closest, up to `radius` blocks away.
+    pub fn safe_spawn(&self, column: IVec2, radius: i32) -> Option<IVec3> {
+        let top = (WORLD_H * CHUNK_SIZE) as i32;
+        let standable = |x: i32, z: i32| {
+            (0..top).rev().find(|&y| {
+                let cube_type = |y: i32| self.get_voxel(IVec3::new(x, y, z)).cube_type;
+                let free = |y: i32| !cube_type(y).is_solid() && !cube_type(y).is_fluid();
+                cube_type(y).is_solid() && free(y + 1) && free(y + 2)
             })
-            .collect();
-        Self { chunks, _voxels }
+        };
+        (0..=radius).find_map(|ring| {
+            // The columns at `ring` blocks away, along the sides of a square
+            (-ring..=ring)
+                .flat_map(|dx| (-ring..=ring).map(move |dz| (dx, dz)))
+                .filter(|(dx, dz)| dx.abs() == ring || dz.abs() == ring)
+                .find_map(|(dx, dz)| {
+                    let (x, z) = (column.x + dx, column.y + dz);
+                    standable(x, z).map(|y| IVec3::new(x, y, z))
+                })
+        })
+    }
+
+    pub fn get_voxel(&self, position: IVec3) -> Voxel {
+        match VxWorldCoord::from_position(position) {
+            Some(world_coord) => self.voxels[world_coord.get_id()],
+            None => Voxel::default(),
+        }
+    }
+
+    /// Replaces the voxel at `position`, flagging every chunk whose mesh depends on it so it is
+    /// rebuilt. Returns false if the position lies outside of the world.
+    pub fn set_voxel(&mut self, position: IVec3, voxel: Voxel) -> bool {
+        let Some(world_coord) = VxWorldCoord::from_position(position) else {
+            return false;
+        };
+        self.voxels[world_coord.get_id()] = voxel;
+        self.edited_chunks.insert(world_coord.chunk_id());
+        self.edits.push(position);
+        self.unsettled.extend([position, position + IVec3::Y]);
+        // Faces and ambient occlusion of the surrounding voxels depend on this one, which may
+        // lie in a neighbouring chunk
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if let Some(neighbour) =
+                        VxWorldCoord::from_position(position + IVec3::new(x, y, z))
+                    {
+                        self.dirty_chunks.insert(neighbour.chunk_id());
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Replaces many voxels at once, for edits too big to go through [VxWorld::set_voxel]: each
+    /// chunk touched is only flagged once, along with its neighbours. Returns the number of voxels
+    /// written, those falling outside of the world being dropped.
+    pub fn set_voxels(&mut self, voxels: impl IntoIterator<Item = (IVec3, Voxel)>) -> usize {
+        self.replace_voxels(voxels, false)
+    }
+
+    /// Replaces the voxel at `position` like [VxWorld::set_voxel], on behalf of the player: the
+    /// edit is kept for the edit history, so it can be undone.
+    pub fn edit_voxel(&mut self, position: IVec3, voxel: Voxel) -> bool {
+        let before = self.get_voxel(position);
+        if !self.set_voxel(position, voxel) {
+            return false;
+        }
+        self.voxel_edits.push(VoxelEdit {
+            position,
+            before,
+            after: voxel,
+        });
+        true
+    }
+
+    /// Replaces many voxels like [VxWorld::set_voxels], on behalf of the player: the edits are
+    /// kept for the edit history, so they can be undone.
+    pub fn edit_voxels(&mut self, voxels: impl IntoIterator<Item = (IVec3, Voxel)>) -> usize {
+        self.replace_voxels(voxels, true)
+    }
+
+    fn replace_voxels(
+        &mut self,
+        voxels: impl IntoIterator<Item = (IVec3, Voxel)>,
+        record: bool,
+    ) -> usize {
+        let mut touched_chunks = HashSet::new();
+        let mut written = 0;
+        for (position, voxel) in voxels {
+            let Some(world_coord) = VxWorldCoord::from_position(position) else {
+                continue;
+            };
+            let previous = std::mem::replace(&mut self.voxels[world_coord.get_id()], voxel);
+            if record {
+                self.voxel_edits.push(VoxelEdit {
+                    position,
+                    before: previous,
+                    after: voxel,
+                });
+            }
+            // Only the block entities need to know about every single edit
+            if previous.cube_type.has_block_entity() || voxel.cube_type.has_block_entity() {
+                self.edits.push(position);
+            }
+            // Blocks subject to gravity may lie on nothing, as may the blocks above removed ones
+            if voxel.cube_type.has_gravity() {
+                self.unsettled.push(position);
+            }
+            if !voxel.cube_type.is_solid() {
+                self.unsettled.push(position + IVec3::Y);
+            }
+            touched_chunks.insert(world_coord.chunk_coord());
+            written += 1;
+        }
+        for (x, y, z) in touched_chunks {
+            self.edited_chunks.extend(chunk_id((x, y, z)));
+            for d_x in -1..=1 {
+                for d_y in -1..=1 {
+                    for d_z in -1..=1 {
+                        let neighbour = (
+                            x.wrapping_add_signed(d_x),
+                            y.wrapping_add_signed(d_y),
+                            z.wrapping_add_signed(d_z),
+                        );
+                        self.dirty_chunks.extend(chunk_id(neighbour));
+                    }
+                }
+            }
+        }
+        written
+    }
+
+    /// Walks through the voxel grid along a ray, and returns the first non empty voxel hit along
+    /// with the face the ray entered it from. Fluids are gone through.
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<(IVec3, FaceType)> {
+        let direction = direction.try_normalize()?;
+        // Voxels are centered on integer coordinates, so we shift the origin to work with cells
+        // spanning from one integer to the next
+        let start = origin + Vec3::splat(0.5);
+        let mut position = start.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+        let delta = direction.recip().abs();
+        let mut next = Vec3::new(
+            first_crossing(start.x, direction.x),
+            first_crossing(start.y, direction.y),
+            first_crossing(start.z, direction.z),
+        );
+        let mut face: Option<FaceType> = None;
+        loop {
+            if let Some(face) = &face {
+                // Fluids are seen and walked through
+                let cube_type = self.get_voxel(position).cube_type;
+                if cube_type != CubeTypes::Empty && !cube_type.is_fluid() {
+                    return Some((position, face.clone()));
+                }
+            }
+            let distance;
+            if next.x < next.y && next.x < next.z {
+                position.x += step.x;
+                distance = next.x;
+                next.x += delta.x;
+                face = Some(if step.x > 0 {
+                    FaceType::Left
+                } else {
+                    FaceType::Right
+                });
+            } else if next.y < next.z {
+                position.y += step.y;
+                distance = next.y;
+                next.y += delta.y;
+                face = Some(if step.y > 0 {
+                    FaceType::Bottom
+                } else {
+                    FaceType::Top
+                });
+            } else {
+                position.z += step.z;
+                distance = next.z;
+                next.z += delta.z;
+                face = Some(if step.z > 0 {
+                    FaceType::Front
+                } else {
+                    FaceType::Back
+                });
+            }
+            if distance > max_distance {
+                return None;
+            }
+        }
     }
 }
 
-fn map_generation() -> Vec<chunk::CubeTypes> {
-    let mut voxels = vec![CubeTypes::Empty; WORLD_VOL * CHUNK_VOLUME];
+// Distance along the ray before it crosses the first cell boundary of one axis
+fn first_crossing(start: f32, direction: f32) -> f32 {
+    if direction > 0.0 {
+        (start.floor() + 1.0 - start) / direction
+    } else if direction < 0.0 {
+        (start - start.floor()) / -direction
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// The position of a chunk in the world, in chunks
+pub fn chunk_coord(chunk_id: usize) -> (usize, usize, usize) {
+    (
+        chunk_id % WORLD_W,
+        chunk_id / WORLD_AREA,
+        (chunk_id % WORLD_AREA) / WORLD_W,
+    )
+}
+
+fn chunk_id(chunk_coord: (usize, usize, usize)) -> Option<usize> {
+    let (x, y, z) = chunk_coord;
+    // Computed lazily, the coordinates past the edges having wrapped around
+    (x < WORLD_W && y < WORLD_H && z < WORLD_D).then(|| x + y * WORLD_AREA + z * WORLD_W)
+}
+
+/// Generates the voxels of the whole world: the terrain and its underground layers, then the ores
+/// and the decoration.
+pub fn map_generation(underground: &UndergroundConfig, terrain: &TerrainGenerator) -> Vec<Voxel> {
+    let mut voxels = vec![Voxel::default(); WORLD_VOL * CHUNK_VOLUME];
     for c_x in 0..WORLD_W {
         for c_y in 0..WORLD_H {
             for c_z in 0..WORLD_D {
                 for x in 0..CHUNK_SIZE {
                     for z in 0..CHUNK_SIZE {
-                        let height = (CHUNK_SIZE as f32)
-                            * (simplex_noise_2d(
-                                0.01 * Vec2::new(
-                                    (x + c_x * CHUNK_SIZE) as f32,
-                                    (z + c_z * CHUNK_SIZE) as f32,
-                                ),
-                            ) + 1.0);
+                        let (world_x, world_z) =
+                            ((x + c_x * CHUNK_SIZE) as f32, (z + c_z * CHUNK_SIZE) as f32);
+                        let height = terrain.height(world_x, world_z);
+                        // The surface is the highest voxel below the height
+                        let surface = height.ceil() as i32 - 1;
+                        let surface_block = terrain.surface_block(world_x, world_z);
                         for y in 0..CHUNK_SIZE {
-                            if ((c_y * CHUNK_SIZE + y) as f32) < height {
+                            let world_y = (c_y * CHUNK_SIZE + y) as i32;
+                            if (world_y as f32) < height {
+                                let cube_type = match surface_block {
+                                    Some(cube_type) if world_y == surface => cube_type,
+                                    _ => underground.layer_at(world_y, surface - world_y),
+                                };
                                 voxels[VxWorldCoord::new((c_x, c_y, c_z), (x, y, z)).get_id()] =
-                                    CubeTypes::Dirt;
+                                    cube_type.into();
                             }
                         }
                     }
@@ -104,6 +481,8 @@ fn map_generation() -> Vec<chunk::CubeTypes> {
             }
         }
     }
+    strata::place_ores(&mut voxels, underground, WORLD_SEED);
+    decoration::decorate(&mut voxels, WORLD_SEED);
     voxels
 }
 
@@ -112,12 +491,22 @@ pub fn spawn_world_model(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ChunkMaterial>>,
     asset_server: Res<AssetServer>,
+    underground: Res<UndergroundConfig>,
+    terrain: Res<TerrainGenerator>,
 ) {
-    let my_world = VxWorld::new();
+    let mut my_world = VxWorld::new(&underground, &terrain);
+    let start = Instant::now();
+    let chunks: Vec<chunk::VxChunkMesh> = (0..WORLD_VOL)
+        .map(|i| chunk::VxChunkMesh::new(chunk_coord(i), &my_world.voxels))
+        .collect();
+    my_world.meshing_time = start.elapsed();
+    my_world.meshed_chunks = chunks.len();
     // Custom chunk
-    for chunk in my_world.chunks {
+    for chunk in chunks {
+        let mesh = meshes.add(chunk.mesh);
+        my_world.chunk_meshes.push(mesh.clone());
         commands.spawn((
-            Mesh3d(meshes.add(chunk.mesh)),
+            Mesh3d(mesh),
             MeshMaterial3d(materials.add(ChunkMaterial {
                 color: LinearRgba::WHITE,
                 color_texture: Some(asset_server.load_with_settings(
@@ -126,6 +515,7 @@ pub fn spawn_world_model(
                         settings.sampler = ImageSampler::nearest();
                     },
                 )),
+                wireframe: false,
             })),
             Transform::from_translation(
                 (CHUNK_SIZE as f32)
@@ -137,4 +527,29 @@ pub fn spawn_world_model(
             ),
         ));
     }
+    commands.insert_resource(my_world);
+}
+
+/// Sent when the mesh of a chunk is built again, its voxels having changed
+#[derive(Debug, Clone, Copy, Event)]
+pub struct ChunkRemeshed(pub usize);
+
+/// Rebuilds the meshes of the chunks edited since the last frame
+pub fn remesh_chunks(
+    mut my_world: ResMut<VxWorld>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut remeshed: EventWriter<ChunkRemeshed>,
+) {
+    if my_world.dirty_chunks.is_empty() {
+        return;
+    }
+    let start = Instant::now();
+    let dirty_chunks: Vec<usize> = my_world.dirty_chunks.drain().collect();
+    for chunk_id in &dirty_chunks {
+        let chunk = chunk::VxChunkMesh::new(chunk_coord(*chunk_id), &my_world.voxels);
+        meshes.insert(&my_world.chunk_meshes[*chunk_id], chunk.mesh);
+        remeshed.write(ChunkRemeshed(*chunk_id));
+    }
+    my_world.meshing_time = start.elapsed();
+    my_world.meshed_chunks = dirty_chunks.len();
 }
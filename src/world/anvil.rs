@@ -0,0 +1,497 @@
+//! Import of Minecraft maps saved in the Anvil format (`r.<x>.<z>.mca` region files).
+//!
+//! A region holds up to 32 by 32 chunk columns. Its first 4 KiB sector is a table giving, for each
+//! column, the sector its data starts at; the data itself is a compressed NBT compound holding
+//! the sections of the column, 16 voxels high, each one storing its block states and biomes as
+//! indices into a palette, packed into arrays of longs.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use bevy::prelude::*;
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use super::{
+    block_names::BlockNames,
+    nbt::{self, Tag},
+    CubeTypes, Voxel, VxWorld,
+};
+
+const SECTOR_SIZE: usize = 4096;
+/// Width of a chunk column, and height of one of its sections
+pub const SECTION_SIZE: i32 = 16;
+const SECTION_VOLUME: usize = 4096;
+// Biomes are stored for cells of 4 by 4 by 4 voxels
+const BIOME_CELLS: usize = 64;
+
+// Data version of 1.18, which moved the sections out of the "Level" compound
+const FLAT_CHUNK_VERSION: i64 = 2844;
+// Data version of 1.16, from which the packed values no longer span two longs
+const PADDED_PACKING_VERSION: i64 = 2527;
+
+/// The sixteen voxels high slice of a chunk column
+#[derive(Debug, Clone)]
+pub struct AnvilSection {
+    /// The height of the section, in sections
+    pub y: i32,
+    /// The voxels of the section, X first, then Z, then Y
+    pub voxels: Vec<Voxel>,
+    /// The names of the biomes found in the section
+    pub biome_palette: Vec<String>,
+    /// For each cell of 4 by 4 by 4 voxels, the index of its biome in the palette. Empty when the
+    /// chunk does not store biomes per section, as before 1.18.
+    pub biomes: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnvilChunk {
+    /// The position of the column, in chunks
+    pub x: i32,
+    pub z: i32,
+    pub sections: Vec<AnvilSection>,
+}
+
+impl AnvilChunk {
+    fn section(&self, y: i32) -> Option<&AnvilSection> {
+        self.sections
+            .iter()
+            .find(|section| section.y == y.div_euclid(SECTION_SIZE))
+    }
+
+    /// The voxel at a position relative to the column, its height being the Minecraft one
+    pub fn voxel(&self, local: IVec3) -> Option<Voxel> {
+        if !(0..SECTION_SIZE).contains(&local.x) || !(0..SECTION_SIZE).contains(&local.z) {
+            return None;
+        }
+        let section = self.section(local.y)?;
+        let (x, y, z) = (local.x, local.y.rem_euclid(SECTION_SIZE), local.z);
+        section
+            .voxels
+            .get((x + z * SECTION_SIZE + y * SECTION_SIZE * SECTION_SIZE) as usize)
+            .copied()
+    }
+
+    /// The name of the biome at a position relative to the column
+    pub fn biome(&self, local: IVec3) -> Option<&str> {
+        if !(0..SECTION_SIZE).contains(&local.x) || !(0..SECTION_SIZE).contains(&local.z) {
+            return None;
+        }
+        let section = self.section(local.y)?;
+        let cell = local.with_y(local.y.rem_euclid(SECTION_SIZE)) / 4;
+        let index = *section
+            .biomes
+            .get((cell.x + cell.z * 4 + cell.y * 16) as usize)?;
+        section
+            .biome_palette
+            .get(index as usize)
+            .map(String::as_str)
+    }
+}
+
+/// The chunk columns of a region file
+#[derive(Debug, Clone, Default)]
+pub struct AnvilRegion {
+    pub chunks: Vec<AnvilChunk>,
+    /// The block names missing from the table, replaced by the placeholder
+    pub unknown_blocks: HashSet<String>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl AnvilRegion {
+    /// Reads every chunk column of a region. The blocks are mapped onto our cube types with
+    /// `names`, those it does not know about becoming `placeholder`. Columns that can not be read
+    /// are skipped with a warning.
+    pub fn read(
+        mut reader: impl Read,
+        names: &BlockNames,
+        placeholder: CubeTypes,
+    ) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if bytes.len() < 2 * SECTOR_SIZE {
+            return Err(invalid_data("region file without header"));
+        }
+        let mut region = Self::default();
+        for location in bytes[..SECTOR_SIZE].chunks_exact(4) {
+            let sector = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize;
+            if sector == 0 || location[3] == 0 {
+                continue;
+            }
+            match read_chunk(&bytes, sector * SECTOR_SIZE, names, placeholder) {
+                Ok((chunk, unknown_blocks)) => {
+                    region.chunks.push(chunk);
+                    region.unknown_blocks.extend(unknown_blocks);
+                }
+                Err(error) => warn!("Skipping a chunk of the region: {error}"),
+            }
+        }
+        if !region.unknown_blocks.is_empty() {
+            warn!(
+                "Unknown blocks replaced by {placeholder:?}: {:?}",
+                region.unknown_blocks
+            );
+        }
+        Ok(region)
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        names: &BlockNames,
+        placeholder: CubeTypes,
+    ) -> io::Result<Self> {
+        Self::read(fs::File::open(path)?, names, placeholder)
+    }
+}
+
+fn read_chunk(
+    bytes: &[u8],
+    offset: usize,
+    names: &BlockNames,
+    placeholder: CubeTypes,
+) -> io::Result<(AnvilChunk, HashSet<String>)> {
+    let header = bytes
+        .get(offset..offset + 5)
+        .ok_or_else(|| invalid_data("chunk outside of the file"))?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    // The length counts the compression byte
+    let payload = bytes
+        .get(offset + 5..offset + 4 + length.max(1))
+        .ok_or_else(|| invalid_data("truncated chunk"))?;
+    let (_, root) = match header[4] {
+        1 => nbt::read(&mut GzDecoder::new(payload))?,
+        2 => nbt::read(&mut ZlibDecoder::new(payload))?,
+        3 => nbt::read(&mut &payload[..])?,
+        compression if compression & 0x80 != 0 => {
+            return Err(invalid_data("chunk stored in an external file"))
+        }
+        _ => return Err(invalid_data("unsupported compression")),
+    };
+
+    let data_version = root.get("DataVersion").and_then(Tag::as_i64).unwrap_or(0);
+    // Older versions nest everything into a "Level" compound, with capitalised names
+    let (level, sections_key, blocks_key, palette_key) = match root.get("Level") {
+        Some(level) if data_version < FLAT_CHUNK_VERSION => {
+            (level, "Sections", "BlockStates", "Palette")
+        }
+        _ => (&root, "sections", "block_states", "palette"),
+    };
+    let position = |key: &str| {
+        level
+            .get(key)
+            .and_then(Tag::as_i64)
+            .map(|value| value as i32)
+            .ok_or_else(|| invalid_data("chunk without position"))
+    };
+    let padded = data_version >= PADDED_PACKING_VERSION;
+
+    let mut unknown_blocks = HashSet::new();
+    let mut sections = Vec::new();
+    for section in level
+        .get(sections_key)
+        .and_then(Tag::as_list)
+        .unwrap_or_default()
+    {
+        let Some(y) = section.get("Y").and_then(Tag::as_i64) else {
+            continue;
+        };
+        // Before 1.18, the palette and the data lie in the section itself
+        let (palette, data) = match section.get(blocks_key) {
+            Some(Tag::Compound(block_states)) => {
+                (block_states.get("palette"), block_states.get("data"))
+            }
+            Some(data) => (section.get(palette_key), Some(data)),
+            None => continue,
+        };
+        let Some(palette) = palette.and_then(Tag::as_list) else {
+            // Sections only holding light
+            continue;
+        };
+        let palette: Vec<Voxel> = palette
+            .iter()
+            .map(|entry| {
+                let name = block_state_string(entry);
+                names.voxel(&name).unwrap_or_else(|| {
+                    unknown_blocks.insert(name.split('[').next().unwrap_or_default().to_string());
+                    placeholder.into()
+                })
+            })
+            .collect();
+        let indices = unpack(
+            data.and_then(Tag::as_long_array).unwrap_or_default(),
+            block_bits(palette.len()),
+            SECTION_VOLUME,
+            padded,
+        );
+        let voxels = indices
+            .into_iter()
+            .map(|index| palette.get(index as usize).copied().unwrap_or_default())
+            .collect();
+
+        let (biome_palette, biomes) = match section.get("biomes") {
+            Some(biomes) => {
+                let palette: Vec<String> = biomes
+                    .get("palette")
+                    .and_then(Tag::as_list)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|name| name.as_str().map(str::to_string))
+                    .collect();
+                let indices = unpack(
+                    biomes
+                        .get("data")
+                        .and_then(Tag::as_long_array)
+                        .unwrap_or_default(),
+                    ceil_log2(palette.len()),
+                    BIOME_CELLS,
+                    true,
+                );
+                (palette, indices)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+        sections.push(AnvilSection {
+            y: y as i32,
+            voxels,
+            biome_palette,
+            biomes,
+        });
+    }
+    Ok((
+        AnvilChunk {
+            x: position("xPos")?,
+            z: position("zPos")?,
+            sections,
+        },
+        unknown_blocks,
+    ))
+}
+
+// Formats a palette entry as a block state string, such as "minecraft:oak_log[axis=x]"
+fn block_state_string(entry: &Tag) -> String {
+    let name = entry
+        .get("Name")
+        .and_then(Tag::as_str)
+        .unwrap_or("minecraft:air");
+    let properties: Vec<String> = entry
+        .get("Properties")
+        .and_then(Tag::as_compound)
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some(format!("{key}={}", value.as_str()?)))
+        .collect();
+    if properties.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name}[{}]", properties.join(","))
+    }
+}
+
+fn ceil_log2(length: usize) -> usize {
+    (usize::BITS - length.saturating_sub(1).leading_zeros()) as usize
+}
+
+// Block states use at least 4 bits per value
+fn block_bits(palette_length: usize) -> usize {
+    ceil_log2(palette_length).max(4)
+}
+
+// Unpacks `count` values of `bits` bits. From 1.16 a value never spans two longs, the remaining
+// high bits of each long being left unused; before, the values are packed back to back.
+fn unpack(data: &[i64], bits: usize, count: usize, padded: bool) -> Vec<u16> {
+    if bits == 0 || data.is_empty() {
+        // A single entry palette does not store any data
+        return vec![0; count];
+    }
+    let mask = (1u64 << bits) - 1;
+    let per_long = 64 / bits;
+    (0..count)
+        .map(|index| {
+            let value = if padded {
+                data.get(index / per_long)
+                    .map_or(0, |long| (*long as u64) >> ((index % per_long) * bits))
+            } else {
+                let bit = index * bits;
+                let (long, shift) = (bit / 64, bit % 64);
+                let low = data.get(long).map_or(0, |long| (*long as u64) >> shift);
+                let high = match (shift + bits > 64, data.get(long + 1)) {
+                    (true, Some(next)) => (*next as u64) << (64 - shift),
+                    _ => 0,
+                };
+                low | high
+            };
+            (value & mask) as u16
+        })
+        .collect()
+}
+
+impl VxWorld {
+    /// Writes the chunk columns of a region into the world, each Minecraft block landing at its
+    /// own position plus `offset`. Blocks outside of the world are dropped, air is written too.
+    /// The import is a single edit, undone at once. Returns the number of voxels written.
+    pub fn import_anvil(&mut self, region: &AnvilRegion, offset: IVec3) -> usize {
+        self.edit_voxels(region.chunks.iter().flat_map(|chunk| {
+            let origin = IVec3::new(chunk.x, 0, chunk.z) * SECTION_SIZE + offset;
+            chunk.sections.iter().flat_map(move |section| {
+                let section_origin = origin + IVec3::Y * section.y * SECTION_SIZE;
+                section
+                    .voxels
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, voxel)| {
+                        let index = index as i32;
+                        let local = IVec3::new(
+                            index % SECTION_SIZE,
+                            index / (SECTION_SIZE * SECTION_SIZE),
+                            (index / SECTION_SIZE) % SECTION_SIZE,
+                        );
+                        (section_origin + local, *voxel)
+                    })
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A region holding two columns: (0, 0) in the 1.20 layout compressed with zlib, and (1, 0) in
+    // the 1.15 layout compressed with gzip. A third entry of its table points past the end of the
+    // file.
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/r.0.0.mca");
+    const PLACEHOLDER: CubeTypes = CubeTypes::MossyCobblestone;
+
+    fn fixture() -> AnvilRegion {
+        AnvilRegion::load(FIXTURE, &BlockNames::default(), PLACEHOLDER).unwrap()
+    }
+
+    fn column(region: &AnvilRegion, x: i32, z: i32) -> &AnvilChunk {
+        region
+            .chunks
+            .iter()
+            .find(|chunk| (chunk.x, chunk.z) == (x, z))
+            .unwrap()
+    }
+
+    #[test]
+    fn sector_table_skips_missing_columns() {
+        let region = fixture();
+        let mut positions: Vec<(i32, i32)> = region
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.x, chunk.z))
+            .collect();
+        positions.sort();
+        assert_eq!(positions, [(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn region_without_header_is_refused() {
+        let bytes = vec![0; SECTOR_SIZE];
+        assert!(AnvilRegion::read(&bytes[..], &BlockNames::default(), PLACEHOLDER).is_err());
+    }
+
+    #[test]
+    fn zlib_column_is_read() {
+        let region = fixture();
+        let chunk = column(&region, 0, 0);
+        let names = BlockNames::default();
+        assert_eq!(
+            chunk.voxel(IVec3::new(5, 0, 7)),
+            Some(CubeTypes::Stone.into())
+        );
+        assert_eq!(
+            chunk.voxel(IVec3::new(1, 1, 2)),
+            names.voxel("minecraft:oak_log[axis=x]")
+        );
+        assert_eq!(chunk.voxel(IVec3::new(1, 2, 2)), Some(Voxel::default()));
+        assert_eq!(chunk.voxel(IVec3::new(16, 0, 0)), None);
+        assert_eq!(chunk.biome(IVec3::new(0, 0, 0)), Some("minecraft:forest"));
+        assert_eq!(chunk.biome(IVec3::new(4, 0, 0)), Some("minecraft:plains"));
+    }
+
+    #[test]
+    fn gzip_column_is_read_unpadded() {
+        let region = fixture();
+        let chunk = column(&region, 1, 0);
+        // The palette index of each voxel is its index modulo 17: air, then fifteen stones, then
+        // a block we do not know
+        for index in [0, 1, 12, 13, 16, 17, 33, 4095] {
+            let local = IVec3::new(index % 16, index / 256, (index / 16) % 16);
+            let expected = match index % 17 {
+                0 => CubeTypes::Empty,
+                16 => PLACEHOLDER,
+                _ => CubeTypes::Stone,
+            };
+            assert_eq!(chunk.voxel(local), Some(expected.into()), "voxel {index}");
+        }
+        assert!(chunk.sections[0].biomes.is_empty());
+    }
+
+    #[test]
+    fn unknown_blocks_become_the_placeholder() {
+        let region = fixture();
+        let mut unknown: Vec<&str> = region.unknown_blocks.iter().map(String::as_str).collect();
+        unknown.sort();
+        assert_eq!(unknown, ["minecraft:glowstone", "minecraft:weird_block"]);
+        assert_eq!(
+            column(&region, 0, 0).voxel(IVec3::new(3, 3, 3)),
+            Some(PLACEHOLDER.into())
+        );
+    }
+
+    #[test]
+    fn import_is_a_single_edit() {
+        let region = fixture();
+        let mut my_world = VxWorld::from_voxels(vec![
+            Voxel::default();
+            crate::WORLD_VOL * crate::CHUNK_VOLUME
+        ]);
+        let offset = IVec3::new(64, 20, 64);
+        let written = my_world.import_anvil(&region, offset);
+        assert!(written > 0);
+        let edits = my_world.take_voxel_edits();
+        assert_eq!(edits.len(), written);
+        let log = IVec3::new(1, 1, 2);
+        assert_eq!(
+            Some(my_world.get_voxel(offset + log)),
+            column(&region, 0, 0).voxel(log)
+        );
+        assert!(edits
+            .iter()
+            .any(|edit| edit.position == offset + log && edit.before == Voxel::default()));
+    }
+
+    #[test]
+    fn unpack_padded() {
+        // Three values of 20 bits per long, the 4 high bits being unused
+        let long = 1 | (2 << 20) | (3 << 40) | (0xF << 60);
+        assert_eq!(unpack(&[long, 4], 20, 4, true), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unpack_unpadded() {
+        // The fourth value of 20 bits spans the two longs
+        let value = 0b1011_0110_0000_0000_0001_u64;
+        let first = 1 | (2 << 20) | (3 << 40) | (value << 60);
+        let second = value >> 4;
+        assert_eq!(
+            unpack(&[first as i64, second as i64], 20, 4, false),
+            [1, 2, 3, value as u16]
+        );
+    }
+
+    #[test]
+    fn single_entry_palette_needs_no_data() {
+        assert_eq!(unpack(&[], 4, 3, true), [0, 0, 0]);
+        assert_eq!(block_bits(1), 4);
+        assert_eq!(block_bits(17), 5);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{CubeTypes, VxWorld};
+
+/// Number of slots of a chest
+pub const CHEST_SLOTS: usize = 27;
+
+/// The component linking an entity to the voxel it holds the data of
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct BlockEntity {
+    pub position: IVec3,
+    pub cube_type: CubeTypes,
+}
+
+/// The text written on a sign
+#[derive(Debug, Clone, Default, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct SignText(pub String);
+
+/// The content of a chest: for each slot, the type of the cubes and how many of them are stored
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct ChestContents(pub Vec<Option<(CubeTypes, u32)>>);
+
+impl Default for ChestContents {
+    fn default() -> Self {
+        Self(vec![None; CHEST_SLOTS])
+    }
+}
+
+/// Finds the block entity holding the data of a voxel from its position
+#[derive(Debug, Default, Resource)]
+pub struct BlockEntities(HashMap<IVec3, Entity>);
+
+impl BlockEntities {
+    pub fn get(&self, position: IVec3) -> Option<Entity> {
+        self.0.get(&position).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IVec3, &Entity)> {
+        self.0.iter()
+    }
+}
+
+/// The data held by a block entity, kept aside while its voxel is gone so it can be put back
+#[derive(Debug, Clone)]
+pub enum BlockEntityData {
+    Chest(ChestContents),
+    Sign(SignText),
+}
+
+impl BlockEntityData {
+    /// Gives the data back to a block entity, replacing what it held
+    pub fn insert_into(self, entity: &mut EntityCommands) {
+        match self {
+            BlockEntityData::Chest(contents) => entity.insert(contents),
+            BlockEntityData::Sign(text) => entity.insert(text),
+        };
+    }
+}
+
+impl CubeTypes {
+    pub fn has_block_entity(&self) -> bool {
+        matches!(self, CubeTypes::Chest | CubeTypes::Sign)
+    }
+}
+
+fn spawn_block_entity(commands: &mut Commands, position: IVec3, cube_type: CubeTypes) -> Entity {
+    let block_entity = BlockEntity {
+        position,
+        cube_type,
+    };
+    match cube_type {
+        CubeTypes::Chest => commands.spawn((block_entity, ChestContents::default())),
+        CubeTypes::Sign => commands.spawn((block_entity, SignText::default())),
+        _ => commands.spawn(block_entity),
+    }
+    .id()
+}
+
+/// Creates and destroys the block entities following the edits of the voxels
+pub fn sync_block_entities(
+    mut commands: Commands,
+    mut my_world: ResMut<VxWorld>,
+    mut block_entities: ResMut<BlockEntities>,
+    q_block_entities: Query<&BlockEntity>,
+) {
+    for position in my_world.take_edits() {
+        let cube_type = my_world.get_voxel(position).cube_type;
+        if let Some(entity) = block_entities.get(position) {
+            if q_block_entities
+                .get(entity)
+                .is_ok_and(|block_entity| block_entity.cube_type == cube_type)
+            {
+                continue;
+            }
+            commands.entity(entity).despawn();
+            block_entities.0.remove(&position);
+        }
+        if cube_type.has_block_entity() {
+            let entity = spawn_block_entity(&mut commands, position, cube_type);
+            block_entities.0.insert(position, entity);
+        }
+    }
+}
+
+/// Indexes the block entities spawned from elsewhere than [sync_block_entities], such as the ones
+/// loaded along with their chunk
+pub fn index_block_entities(
+    mut block_entities: ResMut<BlockEntities>,
+    q_block_entities: Query<(Entity, &BlockEntity), Added<BlockEntity>>,
+) {
+    for (entity, block_entity) in &q_block_entities {
+        block_entities.0.insert(block_entity.position, entity);
+    }
+}
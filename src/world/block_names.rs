@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{
+    block_state::{Axis, Orientation},
+    BlockState, CubeTypes, FaceType, Voxel,
+};
+
+impl CubeTypes {
+    /// The namespaced name of the cube type, as used by Minecraft and the tools around it
+    pub fn name(&self) -> &'static str {
+        match self {
+            CubeTypes::Empty => "minecraft:air",
+            CubeTypes::Dirt => "minecraft:dirt",
+            CubeTypes::OakLog => "minecraft:oak_log",
+            CubeTypes::Furnace => "minecraft:furnace",
+            CubeTypes::Chest => "minecraft:chest",
+            CubeTypes::Sign => "minecraft:oak_sign",
+            CubeTypes::BirchLog => "minecraft:birch_log",
+            CubeTypes::SpruceLog => "minecraft:spruce_log",
+            CubeTypes::JungleLog => "minecraft:jungle_log",
+            CubeTypes::Leaves => "minecraft:oak_leaves",
+            CubeTypes::Cobblestone => "minecraft:cobblestone",
+            CubeTypes::MossyCobblestone => "minecraft:mossy_cobblestone",
+            CubeTypes::Poppy => "minecraft:poppy",
+            CubeTypes::Dandelion => "minecraft:dandelion",
+            CubeTypes::Stone => "minecraft:stone",
+            CubeTypes::Deepslate => "minecraft:deepslate",
+            CubeTypes::CoalOre => "minecraft:coal_ore",
+            CubeTypes::IronOre => "minecraft:iron_ore",
+            CubeTypes::GoldOre => "minecraft:gold_ore",
+            CubeTypes::DiamondOre => "minecraft:diamond_ore",
+            CubeTypes::Water => "minecraft:water",
+            CubeTypes::Lava => "minecraft:lava",
+            CubeTypes::Sand => "minecraft:sand",
+            CubeTypes::Gravel => "minecraft:gravel",
+            CubeTypes::BareDirt => "minecraft:coarse_dirt",
+            CubeTypes::Wheat => "minecraft:wheat",
+            CubeTypes::Ice => "minecraft:ice",
+        }
+    }
+}
+
+// Blocks we do not have, displayed with the closest cube type
+const ALIASES: [(&str, CubeTypes); 27] = [
+    ("minecraft:cave_air", CubeTypes::Empty),
+    ("minecraft:void_air", CubeTypes::Empty),
+    ("minecraft:grass_block", CubeTypes::Dirt),
+    ("minecraft:rooted_dirt", CubeTypes::BareDirt),
+    ("minecraft:farmland", CubeTypes::BareDirt),
+    ("minecraft:podzol", CubeTypes::Dirt),
+    ("minecraft:dirt_path", CubeTypes::Dirt),
+    ("minecraft:red_sand", CubeTypes::Sand),
+    ("minecraft:oak_wood", CubeTypes::OakLog),
+    ("minecraft:birch_wood", CubeTypes::BirchLog),
+    ("minecraft:spruce_wood", CubeTypes::SpruceLog),
+    ("minecraft:jungle_wood", CubeTypes::JungleLog),
+    ("minecraft:oak_wall_sign", CubeTypes::Sign),
+    ("minecraft:birch_leaves", CubeTypes::Leaves),
+    ("minecraft:spruce_leaves", CubeTypes::Leaves),
+    ("minecraft:jungle_leaves", CubeTypes::Leaves),
+    ("minecraft:dark_oak_leaves", CubeTypes::Leaves),
+    ("minecraft:acacia_leaves", CubeTypes::Leaves),
+    ("minecraft:stone_bricks", CubeTypes::Stone),
+    ("minecraft:andesite", CubeTypes::Stone),
+    ("minecraft:diorite", CubeTypes::Stone),
+    ("minecraft:granite", CubeTypes::Stone),
+    ("minecraft:cobbled_deepslate", CubeTypes::Deepslate),
+    ("minecraft:deepslate_coal_ore", CubeTypes::CoalOre),
+    ("minecraft:deepslate_iron_ore", CubeTypes::IronOre),
+    ("minecraft:deepslate_gold_ore", CubeTypes::GoldOre),
+    ("minecraft:deepslate_diamond_ore", CubeTypes::DiamondOre),
+];
+
+/// Maps the block names found in the palettes of other tools, such as `minecraft:oak_log`, onto
+/// our cube types. Every cube type is known by its own [name](CubeTypes::name), and a few
+/// aliases are registered for the common blocks we do not have.
+#[derive(Debug, Clone, Resource)]
+pub struct BlockNames {
+    names: HashMap<String, CubeTypes>,
+}
+
+impl Default for BlockNames {
+    fn default() -> Self {
+        let mut names: HashMap<String, CubeTypes> = ALIASES
+            .iter()
+            .map(|(name, cube_type)| (name.to_string(), *cube_type))
+            .collect();
+        for cube_type in CubeTypes::ALL {
+            names.insert(cube_type.name().to_string(), cube_type);
+        }
+        Self { names }
+    }
+}
+
+impl BlockNames {
+    /// Maps a block name onto a cube type, replacing any previous mapping. Names without a
+    /// namespace are looked up in the `minecraft` one.
+    pub fn insert(&mut self, name: &str, cube_type: CubeTypes) {
+        self.names.insert(namespaced(name), cube_type);
+    }
+
+    pub fn get(&self, name: &str) -> Option<CubeTypes> {
+        self.names.get(&namespaced(name)).copied()
+    }
+
+    /// Parses a block state string such as `minecraft:oak_log[axis=x]`. The properties we
+    /// understand are kept, the others are ignored. Returns None if the block is unknown.
+    pub fn voxel(&self, block_state: &str) -> Option<Voxel> {
+        let (name, properties) = match block_state.split_once('[') {
+            Some((name, properties)) => (name, properties.trim_end_matches(']')),
+            None => (block_state, ""),
+        };
+        let cube_type = self.get(name)?;
+        let mut state = BlockState::default();
+        for (key, value) in properties
+            .split(',')
+            .filter_map(|property| property.split_once('='))
+        {
+            state = match (key.trim(), value.trim()) {
+                ("facing", facing) if cube_type.orientation() == Orientation::Facing => {
+                    match facing_from_name(facing) {
+                        Some(facing) => state.with_facing(facing),
+                        None => state,
+                    }
+                }
+                ("axis", "x") => state.with_axis(Axis::X),
+                ("axis", "y") => state.with_axis(Axis::Y),
+                ("axis", "z") => state.with_axis(Axis::Z),
+                ("open", open) => state.with_open(open == "true"),
+                ("waterlogged", waterlogged) => state.with_waterlogged(waterlogged == "true"),
+                ("level", level) => state.with_level(level.parse().unwrap_or(0)),
+                // The growth of the crops is stored as their level
+                ("age", age) => state.with_level(age.parse().unwrap_or(0)),
+                _ => state,
+            };
+        }
+        Some(Voxel::new(cube_type, state))
+    }
+}
+
+fn namespaced(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("minecraft:{name}")
+    }
+}
+
+// The horizontal facings are named after the cardinal points, north being toward -Z
+fn facing_from_name(name: &str) -> Option<FaceType> {
+    match name {
+        "north" => Some(FaceType::Front),
+        "east" => Some(FaceType::Right),
+        "south" => Some(FaceType::Back),
+        "west" => Some(FaceType::Left),
+        "up" => Some(FaceType::Top),
+        "down" => Some(FaceType::Bottom),
+        _ => None,
+    }
+}
+
+fn facing_name(facing: FaceType) -> &'static str {
+    match facing {
+        FaceType::Front => "north",
+        FaceType::Right => "east",
+        FaceType::Back => "south",
+        FaceType::Left => "west",
+        FaceType::Top => "up",
+        FaceType::Bottom => "down",
+    }
+}
+
+/// Formats a voxel as a block state string, the reverse of [BlockNames::voxel]
+pub fn block_state_name(voxel: Voxel) -> String {
+    let state = voxel.state;
+    let mut properties = Vec::new();
+    match voxel.cube_type.orientation() {
+        Orientation::None => {}
+        Orientation::Facing => properties.push(format!("facing={}", facing_name(state.facing()))),
+        Orientation::Axis => properties.push(format!(
+            "axis={}",
+            match state.axis() {
+                Axis::X => "x",
+                Axis::Y => "y",
+                Axis::Z => "z",
+            }
+        )),
+    }
+    if state.is_open() {
+        properties.push("open=true".to_string());
+    }
+    if state.is_waterlogged() {
+        properties.push("waterlogged=true".to_string());
+    }
+    if state.level() > 0 {
+        let key = match voxel.cube_type {
+            CubeTypes::Wheat => "age",
+            _ => "level",
+        };
+        properties.push(format!("{key}={}", state.level()));
+    }
+    if properties.is_empty() {
+        voxel.cube_type.name().to_string()
+    } else {
+        format!("{}[{}]", voxel.cube_type.name(), properties.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_types_are_found_by_their_names() {
+        let names = BlockNames::default();
+        for cube_type in CubeTypes::ALL {
+            assert_eq!(names.get(cube_type.name()), Some(cube_type));
+        }
+        // Grass has always been exported as dirt, Minecraft's grass block being an alias of it
+        assert_eq!(CubeTypes::Dirt.name(), "minecraft:dirt");
+        assert_eq!(names.get("grass_block"), Some(CubeTypes::Dirt));
+        assert_eq!(
+            names.get("minecraft:coarse_dirt"),
+            Some(CubeTypes::BareDirt)
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use bevy::prelude::*;
+
+use super::chunk::FaceType;
+
+/// The way a block type can be oriented in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    /// The block looks the same from every side
+    None,
+    /// The block has a front face that can point toward one of the four horizontal directions
+    Facing,
+    /// The block is laid along one of the three axis, like a log
+    Axis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Axis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+/// A horizontal flip, applied to a structure before it is rotated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Mirror {
+    #[default]
+    None,
+    /// Flips the X axis, swapping east and west
+    X,
+    /// Flips the Z axis, swapping north and south
+    Z,
+}
+
+/// The small per-voxel state a block can carry (facing, axis, open/closed, waterlogged, level).
+///
+/// Everything is packed into a single byte stored next to the cube type of each voxel:
+///
+/// ```text
+///   7   6   5   4   3   2   1   0
+/// +-----------+---+---+-----------+
+/// |   level   | w | o |facing/axis|
+/// +-----------+---+---+-----------+
+/// ```
+///
+/// A block either has a facing or an axis, never both, so they share the same bits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BlockState(u8);
+
+const ORIENTATION_MASK: u8 = 0b0000_0111;
+const OPEN_BIT: u8 = 0b0000_1000;
+const WATERLOGGED_BIT: u8 = 0b0001_0000;
+const LEVEL_SHIFT: u8 = 5;
+const LEVEL_MASK: u8 = 0b1110_0000;
+
+/// The highest level a block can store
+pub const MAX_LEVEL: u8 = LEVEL_MASK >> LEVEL_SHIFT;
+
+// Horizontal faces ordered by quarter turns around the Y axis, starting from the default facing
+const HORIZONTAL_FACES: [FaceType; 4] = [
+    FaceType::Front,
+    FaceType::Right,
+    FaceType::Back,
+    FaceType::Left,
+];
+
+impl BlockState {
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn facing(&self) -> FaceType {
+        match self.0 & ORIENTATION_MASK {
+            1 => FaceType::Right,
+            2 => FaceType::Back,
+            3 => FaceType::Left,
+            4 => FaceType::Top,
+            5 => FaceType::Bottom,
+            _ => FaceType::Front,
+        }
+    }
+
+    pub fn with_facing(self, facing: FaceType) -> Self {
+        let bits = match facing {
+            FaceType::Front => 0,
+            FaceType::Right => 1,
+            FaceType::Back => 2,
+            FaceType::Left => 3,
+            FaceType::Top => 4,
+            FaceType::Bottom => 5,
+        };
+        Self((self.0 & !ORIENTATION_MASK) | bits)
+    }
+
+    pub fn axis(&self) -> Axis {
+        match self.0 & ORIENTATION_MASK {
+            1 => Axis::X,
+            2 => Axis::Z,
+            _ => Axis::Y,
+        }
+    }
+
+    pub fn with_axis(self, axis: Axis) -> Self {
+        let bits = match axis {
+            Axis::Y => 0,
+            Axis::X => 1,
+            Axis::Z => 2,
+        };
+        Self((self.0 & !ORIENTATION_MASK) | bits)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.0 & OPEN_BIT != 0
+    }
+
+    pub fn with_open(self, open: bool) -> Self {
+        if open {
+            Self(self.0 | OPEN_BIT)
+        } else {
+            Self(self.0 & !OPEN_BIT)
+        }
+    }
+
+    pub fn is_waterlogged(&self) -> bool {
+        self.0 & WATERLOGGED_BIT != 0
+    }
+
+    pub fn with_waterlogged(self, waterlogged: bool) -> Self {
+        if waterlogged {
+            Self(self.0 | WATERLOGGED_BIT)
+        } else {
+            Self(self.0 & !WATERLOGGED_BIT)
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        (self.0 & LEVEL_MASK) >> LEVEL_SHIFT
+    }
+
+    /// Sets the level of the block, saturating at [`MAX_LEVEL`]
+    pub fn with_level(self, level: u8) -> Self {
+        Self((self.0 & !LEVEL_MASK) | (level.min(MAX_LEVEL) << LEVEL_SHIFT))
+    }
+
+    /// Derives the state of a block placed by a player looking toward `look_direction`: facing
+    /// blocks turn their front toward the player, axis blocks are laid along the look direction.
+    pub fn placed(orientation: Orientation, look_direction: Vec3) -> Self {
+        let abs = look_direction.abs();
+        match orientation {
+            Orientation::None => Self::default(),
+            Orientation::Facing => {
+                let facing = if abs.x > abs.z {
+                    if look_direction.x > 0.0 {
+                        FaceType::Left
+                    } else {
+                        FaceType::Right
+                    }
+                } else if look_direction.z > 0.0 {
+                    FaceType::Front
+                } else {
+                    FaceType::Back
+                };
+                Self::default().with_facing(facing)
+            }
+            Orientation::Axis => {
+                let axis = if abs.x >= abs.y && abs.x >= abs.z {
+                    Axis::X
+                } else if abs.z >= abs.y {
+                    Axis::Z
+                } else {
+                    Axis::Y
+                };
+                Self::default().with_axis(axis)
+            }
+        }
+    }
+
+    /// The state of a block once its structure is mirrored, then rotated by `turns` quarter
+    /// turns clockwise around the Y axis (seen from above)
+    pub fn transformed(self, orientation: Orientation, turns: u32, mirror: Mirror) -> Self {
+        match orientation {
+            Orientation::None => self,
+            Orientation::Facing => {
+                let Some(mut index) = HORIZONTAL_FACES.iter().position(|f| *f == self.facing())
+                else {
+                    // Pointing up or down
+                    return self;
+                };
+                index = match (mirror, index) {
+                    (Mirror::X, 1 | 3) | (Mirror::Z, 0 | 2) => (index + 2) % 4,
+                    _ => index,
+                };
+                self.with_facing(HORIZONTAL_FACES[(index + turns as usize) % 4].clone())
+            }
+            Orientation::Axis => match self.axis() {
+                Axis::X if turns % 2 == 1 => self.with_axis(Axis::Z),
+                Axis::Z if turns % 2 == 1 => self.with_axis(Axis::X),
+                _ => self,
+            },
+        }
+    }
+
+    /// Returns which face of the unrotated block model ends up on `face` once the state is
+    /// applied, along with the number of quarter turns its texture has to be rotated by.
+    pub fn local_face(&self, orientation: Orientation, face: FaceType) -> (FaceType, u32) {
+        match orientation {
+            Orientation::None => (face, 0),
+            Orientation::Facing => match self.facing() {
+                // Pointing up or down: the model is tipped over around the X axis
+                FaceType::Top => match face {
+                    FaceType::Top => (FaceType::Front, 0),
+                    FaceType::Front => (FaceType::Bottom, 0),
+                    FaceType::Bottom => (FaceType::Back, 2),
+                    FaceType::Back => (FaceType::Top, 2),
+                    FaceType::Right => (face, 1),
+                    FaceType::Left => (face, 3),
+                },
+                FaceType::Bottom => match face {
+                    FaceType::Bottom => (FaceType::Front, 0),
+                    FaceType::Front => (FaceType::Top, 0),
+                    FaceType::Top => (FaceType::Back, 2),
+                    FaceType::Back => (FaceType::Bottom, 2),
+                    FaceType::Right => (face, 3),
+                    FaceType::Left => (face, 1),
+                },
+                facing => {
+                    let turns = HORIZONTAL_FACES
+                        .iter()
+                        .position(|f| *f == facing)
+                        .unwrap_or(0);
+                    match face {
+                        FaceType::Top => (face, turns as u32),
+                        FaceType::Bottom => (face, ((4 - turns) % 4) as u32),
+                        _ => {
+                            let index = HORIZONTAL_FACES
+                                .iter()
+                                .position(|f| *f == face)
+                                .unwrap_or(0);
+                            (HORIZONTAL_FACES[(index + 4 - turns) % 4].clone(), 0)
+                        }
+                    }
+                }
+            },
+            Orientation::Axis => match self.axis() {
+                Axis::Y => (face, 0),
+                // Laid along X: the model is rolled around the Z axis
+                Axis::X => match face {
+                    FaceType::Right => (FaceType::Top, 0),
+                    FaceType::Left => (FaceType::Bottom, 0),
+                    FaceType::Top => (FaceType::Left, 0),
+                    FaceType::Bottom => (FaceType::Right, 0),
+                    FaceType::Back | FaceType::Front => (face, 1),
+                },
+                // Laid along Z: the model is rolled around the X axis
+                Axis::Z => match face {
+                    FaceType::Back => (FaceType::Top, 0),
+                    FaceType::Front => (FaceType::Bottom, 0),
+                    FaceType::Top => (FaceType::Back, 1),
+                    FaceType::Bottom => (FaceType::Front, 1),
+                    FaceType::Right | FaceType::Left => (face, 1),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faces() -> [FaceType; 6] {
+        [
+            FaceType::Top,
+            FaceType::Bottom,
+            FaceType::Right,
+            FaceType::Left,
+            FaceType::Back,
+            FaceType::Front,
+        ]
+    }
+
+    fn normal(face: FaceType) -> Vec3 {
+        let (x, y, z): (i8, i8, i8) = face.into();
+        Vec3::new(x as f32, y as f32, z as f32)
+    }
+
+    #[test]
+    fn setters_round_trip_and_keep_the_other_bits() {
+        let full = BlockState::from_bits(u8::MAX);
+        for face in faces() {
+            let state = full.with_facing(face.clone());
+            assert_eq!(state.facing(), face);
+            assert!(state.is_open() && state.is_waterlogged());
+            assert_eq!(state.level(), MAX_LEVEL);
+        }
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let state = full.with_axis(axis);
+            assert_eq!(state.axis(), axis);
+            assert_eq!(state.bits() & !ORIENTATION_MASK, !ORIENTATION_MASK);
+        }
+        let state = BlockState::default()
+            .with_facing(FaceType::Left)
+            .with_open(true)
+            .with_level(5);
+        assert!(state.is_open() && !state.is_waterlogged());
+        assert_eq!(
+            state.with_waterlogged(true).bits(),
+            state.bits() | WATERLOGGED_BIT
+        );
+        assert_eq!(state.with_open(false).bits(), state.bits() & !OPEN_BIT);
+        assert_eq!(state.with_level(MAX_LEVEL + 10).level(), MAX_LEVEL);
+        assert_eq!(state.with_level(0).facing(), FaceType::Left);
+        assert_eq!(state.with_level(0).with_level(5), state);
+    }
+
+    #[test]
+    fn placed_blocks_face_the_player() {
+        for look in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Z,
+            Vec3::NEG_Z,
+            Vec3::new(0.3, -0.9, -0.5),
+            Vec3::new(-2.0, 0.5, 1.0),
+        ] {
+            let facing = BlockState::placed(Orientation::Facing, look).facing();
+            let horizontal = Vec3::new(look.x, 0.0, look.z);
+            // The front is the horizontal face the most turned toward the player
+            let front = normal(facing);
+            assert!(faces()
+                .into_iter()
+                .all(|face| front.dot(horizontal) <= normal(face).dot(horizontal)));
+        }
+        let axis = |look| BlockState::placed(Orientation::Axis, look).axis();
+        assert_eq!(axis(Vec3::new(0.9, 0.2, -0.3)), Axis::X);
+        assert_eq!(axis(Vec3::new(0.1, -0.9, 0.3)), Axis::Y);
+        assert_eq!(axis(Vec3::new(0.1, 0.5, -0.8)), Axis::Z);
+        assert_eq!(
+            BlockState::placed(Orientation::None, Vec3::X),
+            BlockState::default()
+        );
+    }
+
+    #[test]
+    fn four_quarter_turns_change_nothing() {
+        let mut states: Vec<(Orientation, BlockState)> = faces()
+            .into_iter()
+            .map(|face| {
+                let state = BlockState::default().with_facing(face).with_level(3);
+                (Orientation::Facing, state)
+            })
+            .collect();
+        states.extend(
+            [Axis::X, Axis::Y, Axis::Z]
+                .map(|axis| (Orientation::Axis, BlockState::default().with_axis(axis))),
+        );
+        for (orientation, state) in states {
+            let mut turned = state;
+            for turn in 1..=4 {
+                turned = turned.transformed(orientation, 1, Mirror::None);
+                assert_eq!(turned, state.transformed(orientation, turn, Mirror::None));
+            }
+            assert_eq!(turned, state);
+            for mirror in [Mirror::X, Mirror::Z] {
+                let mirrored = state.transformed(orientation, 0, mirror);
+                assert_eq!(mirrored.transformed(orientation, 0, mirror), state);
+            }
+        }
+        let north = BlockState::default().with_facing(FaceType::Front);
+        let turned = |turns, mirror| north.transformed(Orientation::Facing, turns, mirror);
+        assert_eq!(turned(1, Mirror::None).facing(), FaceType::Right);
+        assert_eq!(turned(0, Mirror::X).facing(), FaceType::Front);
+        assert_eq!(turned(1, Mirror::Z).facing(), FaceType::Left);
+    }
+
+    #[test]
+    fn every_face_shows_a_distinct_model_face() {
+        let mut states: Vec<(Orientation, BlockState)> = faces()
+            .into_iter()
+            .map(|face| (Orientation::Facing, BlockState::default().with_facing(face)))
+            .collect();
+        states.extend(
+            [Axis::X, Axis::Y, Axis::Z]
+                .map(|axis| (Orientation::Axis, BlockState::default().with_axis(axis))),
+        );
+        states.push((Orientation::None, BlockState::default()));
+        for (orientation, state) in states {
+            let model_faces: Vec<FaceType> = faces()
+                .into_iter()
+                .map(|face| state.local_face(orientation, face).0)
+                .collect();
+            for face in faces() {
+                assert_eq!(
+                    model_faces.iter().filter(|model| **model == face).count(),
+                    1,
+                    "{orientation:?} {state:?}"
+                );
+            }
+            if orientation == Orientation::Facing {
+                assert_eq!(
+                    state.local_face(orientation, state.facing()).0,
+                    FaceType::Front
+                );
+            }
+        }
+        // Logs show their rings at both ends of their axis
+        let log = BlockState::default().with_axis(Axis::X);
+        assert_eq!(
+            log.local_face(Orientation::Axis, FaceType::Right).0,
+            FaceType::Top
+        );
+        assert_eq!(
+            log.local_face(Orientation::Axis, FaceType::Left).0,
+            FaceType::Bottom
+        );
+    }
+}
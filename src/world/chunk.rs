@@ -1,15 +1,226 @@
 use bevy::{asset::RenderAssetUsages, prelude::*, render::mesh::PrimitiveTopology};
 
 use super::{
+    block_state::{BlockState, Orientation},
     ATTRIBUTE_VX_AO, ATTRIBUTE_VX_TYPE, CHUNK_AREA, CHUNK_SIZE, CHUNK_VOLUME, WORLD_AREA, WORLD_D,
     WORLD_H, WORLD_W,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Reflect)]
 pub enum CubeTypes {
+    #[default]
     Empty,
     Dirt,
-    // Stone,
+    OakLog,
+    Furnace,
+    Chest,
+    Sign,
+    BirchLog,
+    SpruceLog,
+    JungleLog,
+    Leaves,
+    Cobblestone,
+    MossyCobblestone,
+    Poppy,
+    Dandelion,
+    Stone,
+    Deepslate,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    Water,
+    Lava,
+    Sand,
+    Gravel,
+    /// Dirt without grass on top, which the grass spreads back onto
+    BareDirt,
+    /// A crop, growing through the levels of its state
+    Wheat,
+    Ice,
+}
+
+/// The geometry used to display a cube type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// A full cube hiding the faces of its neighbours
+    Cube,
+    /// Two crossed quads, used by plants
+    Cross,
+    /// A cube the player goes through, hiding only the faces it shares with the same fluid
+    Fluid,
+}
+
+impl CubeTypes {
+    /// Every cube type, ordered by their ID
+    pub const ALL: [CubeTypes; 27] = [
+        CubeTypes::Empty,
+        CubeTypes::Dirt,
+        CubeTypes::OakLog,
+        CubeTypes::Furnace,
+        CubeTypes::Chest,
+        CubeTypes::Sign,
+        CubeTypes::BirchLog,
+        CubeTypes::SpruceLog,
+        CubeTypes::JungleLog,
+        CubeTypes::Leaves,
+        CubeTypes::Cobblestone,
+        CubeTypes::MossyCobblestone,
+        CubeTypes::Poppy,
+        CubeTypes::Dandelion,
+        CubeTypes::Stone,
+        CubeTypes::Deepslate,
+        CubeTypes::CoalOre,
+        CubeTypes::IronOre,
+        CubeTypes::GoldOre,
+        CubeTypes::DiamondOre,
+        CubeTypes::Water,
+        CubeTypes::Lava,
+        CubeTypes::Sand,
+        CubeTypes::Gravel,
+        CubeTypes::BareDirt,
+        CubeTypes::Wheat,
+        CubeTypes::Ice,
+    ];
+
+    /// The identifier of the cube type, as stored in save files
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<CubeTypes> {
+        CubeTypes::ALL.get(id as usize).copied()
+    }
+
+    pub fn shape(&self) -> Shape {
+        match self {
+            CubeTypes::Poppy | CubeTypes::Dandelion | CubeTypes::Wheat => Shape::Cross,
+            CubeTypes::Water | CubeTypes::Lava => Shape::Fluid,
+            _ => Shape::Cube,
+        }
+    }
+
+    pub fn is_fluid(&self) -> bool {
+        self.shape() == Shape::Fluid
+    }
+
+    /// Whether the player collides with the cube and can stand on it
+    pub fn is_solid(&self) -> bool {
+        *self != CubeTypes::Empty && self.shape() == Shape::Cube
+    }
+
+    /// The damage the cube deals each second to a player touching it
+    pub fn damage(&self) -> f32 {
+        match self {
+            CubeTypes::Lava => 4.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the cube hides the faces of the cubes next to it
+    pub fn is_opaque(&self) -> bool {
+        *self != CubeTypes::Empty && self.shape() == Shape::Cube
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        match self {
+            CubeTypes::OakLog
+            | CubeTypes::BirchLog
+            | CubeTypes::SpruceLog
+            | CubeTypes::JungleLog => Orientation::Axis,
+            CubeTypes::Furnace | CubeTypes::Chest | CubeTypes::Sign => Orientation::Facing,
+            _ => Orientation::None,
+        }
+    }
+
+    /// The average colour of the texture of the cube type, seen from the side
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            CubeTypes::Empty => [0, 0, 0],
+            CubeTypes::Dirt => [99, 160, 143],
+            CubeTypes::OakLog => [102, 81, 49],
+            CubeTypes::Furnace => [113, 113, 113],
+            CubeTypes::Chest => [117, 85, 27],
+            CubeTypes::Sign => [156, 127, 78],
+            CubeTypes::BirchLog => [206, 206, 201],
+            CubeTypes::SpruceLog => [45, 28, 12],
+            CubeTypes::JungleLog => [87, 67, 26],
+            CubeTypes::Leaves => [151, 153, 36],
+            CubeTypes::Cobblestone => [122, 122, 122],
+            CubeTypes::MossyCobblestone => [103, 121, 103],
+            CubeTypes::Poppy => [100, 57, 4],
+            CubeTypes::Dandelion => [108, 162, 0],
+            CubeTypes::Stone => [158, 164, 176],
+            CubeTypes::Deepslate => [64, 60, 60],
+            CubeTypes::CoalOre => [115, 115, 115],
+            CubeTypes::IronOre => [135, 130, 126],
+            CubeTypes::GoldOre => [143, 139, 124],
+            CubeTypes::DiamondOre => [129, 140, 143],
+            CubeTypes::Water => [48, 71, 244],
+            CubeTypes::Lava => [207, 91, 20],
+            CubeTypes::Sand => [219, 211, 160],
+            CubeTypes::Gravel => [132, 122, 121],
+            CubeTypes::BareDirt => [134, 96, 67],
+            CubeTypes::Wheat => [86, 102, 7],
+            CubeTypes::Ice => [165, 194, 245],
+        }
+    }
+
+    /// The index of the tile of the atlas showing the cube from the front, used as its icon
+    pub fn icon(&self) -> Option<usize> {
+        let (row, column, _) = face_tile(*self, &FaceType::Front)?;
+        Some((row * 32 + column) as usize)
+    }
+
+    /// How hard the cube is to break, the time it takes growing with it. Plants break at once,
+    /// fluids cannot be targeted.
+    pub fn hardness(&self) -> f32 {
+        match self {
+            CubeTypes::Empty
+            | CubeTypes::Poppy
+            | CubeTypes::Dandelion
+            | CubeTypes::Wheat
+            | CubeTypes::Water
+            | CubeTypes::Lava => 0.0,
+            CubeTypes::Leaves => 0.2,
+            CubeTypes::Dirt | CubeTypes::BareDirt | CubeTypes::Sand | CubeTypes::Ice => 0.5,
+            CubeTypes::Gravel => 0.6,
+            CubeTypes::Sign => 1.0,
+            CubeTypes::Stone => 1.5,
+            CubeTypes::OakLog
+            | CubeTypes::BirchLog
+            | CubeTypes::SpruceLog
+            | CubeTypes::JungleLog
+            | CubeTypes::Cobblestone
+            | CubeTypes::MossyCobblestone => 2.0,
+            CubeTypes::Chest => 2.5,
+            CubeTypes::Deepslate
+            | CubeTypes::CoalOre
+            | CubeTypes::IronOre
+            | CubeTypes::GoldOre
+            | CubeTypes::DiamondOre => 3.0,
+            CubeTypes::Furnace => 3.5,
+        }
+    }
+}
+
+/// A single cell of the voxel grid: the type of the cube and its state
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Voxel {
+    pub cube_type: CubeTypes,
+    pub state: BlockState,
+}
+
+impl Voxel {
+    pub fn new(cube_type: CubeTypes, state: BlockState) -> Self {
+        Self { cube_type, state }
+    }
+}
+
+impl From<CubeTypes> for Voxel {
+    fn from(cube_type: CubeTypes) -> Self {
+        Self::new(cube_type, BlockState::default())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,7 +253,7 @@ pub struct VxChunkMesh {
 }
 
 impl VxChunkMesh {
-    pub fn new(coord: (usize, usize, usize), voxels: &[CubeTypes]) -> Self {
+    pub fn new(coord: (usize, usize, usize), voxels: &[Voxel]) -> Self {
         let (vertices_coord, uv_coord, vertices_normal, vertices_order, vertices_type, vertices_ao) =
             build_mesh(voxels, &coord);
         // println!("Created chunk with coord {:?}", coord);
@@ -78,6 +289,46 @@ impl VxWorldCoord {
         }
     }
 
+    /// Finds the voxel at a given block position of the world, if it lies inside of the world
+    pub fn from_position(position: IVec3) -> Option<VxWorldCoord> {
+        let size = CHUNK_SIZE as i32;
+        if position.x < 0
+            || position.y < 0
+            || position.z < 0
+            || position.x >= WORLD_W as i32 * size
+            || position.y >= WORLD_H as i32 * size
+            || position.z >= WORLD_D as i32 * size
+        {
+            return None;
+        }
+        let (chunk, cube) = (position / size, position % size);
+        Some(VxWorldCoord::new(
+            (chunk.x as usize, chunk.y as usize, chunk.z as usize),
+            (cube.x as usize, cube.y as usize, cube.z as usize),
+        ))
+    }
+
+    pub fn position(&self) -> IVec3 {
+        IVec3::new(
+            (self.chunk_coord.0 * CHUNK_SIZE + self.cube_coord.0) as i32,
+            (self.chunk_coord.1 * CHUNK_SIZE + self.cube_coord.1) as i32,
+            (self.chunk_coord.2 * CHUNK_SIZE + self.cube_coord.2) as i32,
+        )
+    }
+
+    pub fn chunk_coord(&self) -> (usize, usize, usize) {
+        self.chunk_coord
+    }
+
+    pub fn cube_coord(&self) -> (usize, usize, usize) {
+        self.cube_coord
+    }
+
+    /// The index of the chunk holding this voxel, in the order the chunks are stored
+    pub fn chunk_id(&self) -> usize {
+        self.chunk_coord.0 + self.chunk_coord.1 * WORLD_AREA + self.chunk_coord.2 * WORLD_W
+    }
+
     fn move_direction(&self, direction: &(i8, i8, i8)) -> Option<VxWorldCoord> {
         let mut new_world_coord = Self { ..*self };
         // Index due to X component
@@ -175,26 +426,39 @@ impl VxWorldCoord {
     }
 }
 
-fn get_cube_type(voxels: &[CubeTypes], world_coord: &VxWorldCoord) -> CubeTypes {
+fn get_voxel(voxels: &[Voxel], world_coord: &VxWorldCoord) -> Voxel {
     // let index = chunk_coord.dot(Vec3::new(1.0, WORLD_AREA as f32, WORLD_W as f32)) as usize
     //     * CHUNK_VOLUME
     //     + cube_coord.dot(Vec3::new(1.0, CHUNK_AREA as f32, CHUNK_SIZE as f32)) as usize;
 
     match world_coord.move_direction(&(0, 0, 0)) {
-        Some(new_world_coord) => voxels[new_world_coord.get_id()].clone(),
-        None => CubeTypes::Empty,
+        Some(new_world_coord) => voxels[new_world_coord.get_id()],
+        None => Voxel::default(),
     }
 }
 
-fn is_void(voxels: &[CubeTypes], world_coord: &VxWorldCoord, direction: &(i8, i8, i8)) -> bool {
+fn is_void(voxels: &[Voxel], world_coord: &VxWorldCoord, direction: &(i8, i8, i8)) -> bool {
     match world_coord.move_direction(direction) {
-        Some(new_world_coord) => voxels[new_world_coord.get_id()] == CubeTypes::Empty,
+        Some(new_world_coord) => !voxels[new_world_coord.get_id()].cube_type.is_opaque(),
         None => true,
     }
 }
 
+// Whether the face of a cube towards `direction` is seen: the neighbouring cube does not hide it,
+// and is not the same fluid, the inside of a body of fluid having no faces
+fn is_face_visible(voxels: &[Voxel], world_coord: &VxWorldCoord, direction: &(i8, i8, i8)) -> bool {
+    let cube_type = voxels[world_coord.get_id()].cube_type;
+    match world_coord.move_direction(direction) {
+        Some(new_world_coord) if cube_type.is_fluid() => {
+            voxels[new_world_coord.get_id()].cube_type != cube_type
+                && is_void(voxels, world_coord, direction)
+        }
+        _ => is_void(voxels, world_coord, direction),
+    }
+}
+
 fn get_ao(
-    voxels: &[CubeTypes],
+    voxels: &[Voxel],
     world_coord: &VxWorldCoord,
     face_type: FaceType,
 ) -> (u32, u32, u32, u32) {
@@ -284,13 +548,19 @@ fn get_ao(
     }
 }
 
-fn map_texture(uv_coord: &mut Vec<Vec2>, coord_x: u32, coord_y: u32) {
+fn map_texture(uv_coord: &mut Vec<Vec2>, coord_x: u32, coord_y: u32, rotation: u32) {
     if coord_x < 32 && coord_y < 32 {
         let (x, y) = (coord_x as f32, coord_y as f32);
-        uv_coord.push(Vec2::new(y / 32.0, (x + 1.0) / 32.0));
-        uv_coord.push(Vec2::new(y / 32.0, x / 32.0));
-        uv_coord.push(Vec2::new((y + 1.0) / 32.0, x / 32.0));
-        uv_coord.push(Vec2::new((y + 1.0) / 32.0, (x + 1.0) / 32.0));
+        let corners = [
+            Vec2::new(y / 32.0, (x + 1.0) / 32.0),
+            Vec2::new(y / 32.0, x / 32.0),
+            Vec2::new((y + 1.0) / 32.0, x / 32.0),
+            Vec2::new((y + 1.0) / 32.0, (x + 1.0) / 32.0),
+        ];
+        // The corners go around the tile, so shifting them rotates the texture by quarter turns
+        for i in 0..4 {
+            uv_coord.push(corners[(i + rotation as usize) % 4]);
+        }
     }
 }
 
@@ -303,7 +573,7 @@ fn add_face(
     vertices_ao: &mut Vec<u32>,
     face_type: FaceType,
     face_ao: (u32, u32, u32, u32),
-    cube_type: &CubeTypes,
+    voxel: &Voxel,
     cube_center: Vec3,
 ) {
     let v0: Vec3;
@@ -413,23 +683,145 @@ fn add_face(
         vertices_order.push(offset + offset_increment);
     }
 
-    match cube_type {
-        CubeTypes::Dirt => match face_type {
-            FaceType::Top => map_texture(uv_coord, 11, 16),
-            FaceType::Bottom => map_texture(uv_coord, 6, 8),
-            _ => map_texture(uv_coord, 10, 12),
-        },
-        // CubeTypes::Stone => {
-        //     map_texture(uv_coord, 5, 3);
-        // }
-        CubeTypes::Empty => {
-            println!("Representing empty cube ? {:?}", cube_center);
+    map_cube_texture(uv_coord, voxel, face_type);
+}
+
+/// Adds the two crossed quads of a plant, both visible from either side
+#[allow(clippy::too_many_arguments)]
+fn add_cross(
+    vertices_coord: &mut Vec<Vec3>,
+    uv_coord: &mut Vec<Vec2>,
+    vertices_normal: &mut Vec<Vec3>,
+    vertices_order: &mut Vec<u32>,
+    vertices_type: &mut Vec<u32>,
+    vertices_ao: &mut Vec<u32>,
+    voxel: &Voxel,
+    cube_center: Vec3,
+) {
+    let quads = [
+        [
+            Vec3::new(-0.5, -0.5, -0.5),
+            Vec3::new(-0.5, 0.5, -0.5),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(0.5, -0.5, 0.5),
+        ],
+        [
+            Vec3::new(-0.5, -0.5, 0.5),
+            Vec3::new(-0.5, 0.5, 0.5),
+            Vec3::new(0.5, 0.5, -0.5),
+            Vec3::new(0.5, -0.5, -0.5),
+        ],
+    ];
+    for quad in quads {
+        let offset = vertices_coord.len() as u32;
+        for vx in quad {
+            vertices_coord.push(vx + cube_center);
+            vertices_normal.push(Vec3::Y);
+            // Plants are lit as the top of a cube, without any ambient occlusion
+            vertices_type.push(0);
+            vertices_ao.push(3);
         }
+        for offset_increment in [0, 1, 2, 2, 3, 0, 0, 3, 2, 2, 1, 0] {
+            vertices_order.push(offset + offset_increment);
+        }
+        map_cube_texture(uv_coord, voxel, FaceType::Front);
+    }
+}
+
+fn map_cube_texture(uv_coord: &mut Vec<Vec2>, voxel: &Voxel, face_type: FaceType) {
+    // The texture is picked from the face of the unrotated model, then turned to follow the state
+    let (local_face, rotation) = voxel
+        .state
+        .local_face(voxel.cube_type.orientation(), face_type);
+    match face_tile(voxel.cube_type, &local_face) {
+        Some((row, column, turns)) => map_texture(
+            uv_coord,
+            grown_row(voxel, row),
+            column,
+            (rotation + turns) % 4,
+        ),
+        None => println!("Representing empty cube ?"),
+    }
+}
+
+// Crops show how far they grew, their stages following each other down the atlas
+fn grown_row(voxel: &Voxel, row: u32) -> u32 {
+    match voxel.cube_type {
+        CubeTypes::Wheat => row + voxel.state.level() as u32,
+        _ => row,
     }
 }
 
+impl Voxel {
+    /// The tile of the atlas (row and column) showing the side of the voxel, None for empty ones
+    pub fn side_tile(&self) -> Option<(u32, u32)> {
+        face_tile(self.cube_type, &FaceType::Front)
+            .map(|(row, column, _)| (grown_row(self, row), column))
+    }
+}
+
+// The tile of the atlas (row and column) showing a face of the unrotated model, along with the
+// quarter turns it is laid sideways by
+pub(super) fn face_tile(cube_type: CubeTypes, face: &FaceType) -> Option<(u32, u32, u32)> {
+    let tile = match cube_type {
+        CubeTypes::Dirt => match face {
+            FaceType::Top => (11, 16, 0),
+            FaceType::Bottom => (6, 8, 0),
+            _ => (10, 12, 0),
+        },
+        CubeTypes::OakLog => match face {
+            FaceType::Top | FaceType::Bottom => (14, 4, 0),
+            _ => (14, 3, 0),
+        },
+        CubeTypes::BirchLog => match face {
+            FaceType::Top | FaceType::Bottom => (14, 0, 0),
+            _ => (13, 15, 0),
+        },
+        CubeTypes::SpruceLog => match face {
+            FaceType::Top | FaceType::Bottom => (14, 6, 0),
+            _ => (14, 5, 0),
+        },
+        CubeTypes::JungleLog => match face {
+            FaceType::Top | FaceType::Bottom => (14, 2, 0),
+            // This bark is laid sideways in the atlas
+            _ => (14, 1, 1),
+        },
+        CubeTypes::Leaves => (14, 10, 0),
+        CubeTypes::Cobblestone => (5, 3, 0),
+        CubeTypes::MossyCobblestone => (5, 4, 0),
+        CubeTypes::Poppy => (6, 15, 0),
+        CubeTypes::Dandelion => (2, 15, 0),
+        CubeTypes::Stone => (4, 7, 0),
+        CubeTypes::Deepslate => (2, 3, 0),
+        CubeTypes::CoalOre => (5, 1, 0),
+        CubeTypes::IronOre => (12, 11, 0),
+        CubeTypes::GoldOre => (10, 11, 0),
+        CubeTypes::DiamondOre => (5, 8, 0),
+        CubeTypes::Furnace => match face {
+            FaceType::Front => (8, 3, 0),
+            FaceType::Top | FaceType::Bottom => (8, 6, 0),
+            _ => (8, 5, 0),
+        },
+        CubeTypes::Chest => match face {
+            FaceType::Front => (29, 2, 0),
+            _ => (29, 1, 0),
+        },
+        CubeTypes::Sign => (4, 16, 0),
+        CubeTypes::Water => (0, 2, 0),
+        CubeTypes::Lava => (0, 0, 0),
+        CubeTypes::Sand => (7, 19, 0),
+        CubeTypes::Gravel => (29, 3, 0),
+        CubeTypes::BareDirt => (6, 8, 0),
+        // The first stage of the growth, the next ones being below it
+        CubeTypes::Wheat => (2, 22, 0),
+        CubeTypes::Ice => (12, 8, 0),
+        CubeTypes::Empty => return None,
+    };
+    Some(tile)
+}
+
 fn build_mesh(
-    voxels: &[CubeTypes],
+    voxels: &[Voxel],
     chunk_coord: &(usize, usize, usize),
 ) -> (
     Vec<Vec3>,
@@ -459,42 +851,53 @@ fn build_mesh(
             for p_z in 0..CHUNK_SIZE {
                 // let cube_coord = Vec3::new(p_x as f32, p_y as f32, p_z as f32);
                 let world_coord = VxWorldCoord::new(chunk_coord.clone(), (p_x, p_y, p_z));
-                if get_cube_type(voxels, &world_coord) != CubeTypes::Empty {
-                    let cube_type = get_cube_type(voxels, &world_coord);
+                let voxel = get_voxel(voxels, &world_coord);
+                if voxel.cube_type.shape() == Shape::Cross {
+                    add_cross(
+                        &mut vertices_coord,
+                        &mut uv_coord,
+                        &mut vertices_normal,
+                        &mut vertices_order,
+                        &mut vertices_type,
+                        &mut vertices_ao,
+                        &voxel,
+                        Vec3::new(p_x as f32, p_y as f32, p_z as f32),
+                    );
+                } else if voxel.cube_type != CubeTypes::Empty {
                     let mut face_to_add: Vec<(FaceType, (u32, u32, u32, u32))> = Vec::new();
 
                     // Top vertices
-                    if is_void(voxels, &world_coord, &FaceType::Top.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Top.into()) {
                         let face_type = FaceType::Top;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
                     }
                     // Bottom vertices_coord
-                    if is_void(voxels, &world_coord, &FaceType::Bottom.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Bottom.into()) {
                         let face_type = FaceType::Bottom;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
                     }
                     // Right vertices_coord
-                    if is_void(voxels, &world_coord, &FaceType::Right.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Right.into()) {
                         let face_type = FaceType::Right;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
                     }
                     // Left vertices_coord
-                    if is_void(voxels, &world_coord, &FaceType::Left.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Left.into()) {
                         let face_type = FaceType::Left;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
                     }
                     // Back vertices_coord
-                    if is_void(voxels, &world_coord, &FaceType::Back.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Back.into()) {
                         let face_type = FaceType::Back;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
                     }
                     // Front vertices_coord
-                    if is_void(voxels, &world_coord, &FaceType::Front.into()) {
+                    if is_face_visible(voxels, &world_coord, &FaceType::Front.into()) {
                         let face_type = FaceType::Front;
                         face_to_add
                             .push((face_type.clone(), get_ao(voxels, &world_coord, face_type)));
@@ -509,7 +912,7 @@ fn build_mesh(
                             &mut vertices_ao,
                             face_type,
                             face_ao,
-                            &cube_type,
+                            &voxel,
                             Vec3::new(p_x as f32, p_y as f32, p_z as f32),
                         );
                     }
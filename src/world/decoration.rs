@@ -0,0 +1,267 @@
+use bevy::prelude::*;
+
+use super::{
+    block_state::Axis, BlockState, CubeTypes, Voxel, VxWorldCoord, CHUNK_SIZE, WORLD_D, WORLD_H,
+    WORLD_W,
+};
+
+/// A small deterministic random number generator (SplitMix64), seeded for each chunk column.
+/// Along with the ground being picked from the undecorated terrain, where the features of a chunk
+/// grow does not depend on the order the chunks are decorated in.
+#[derive(Debug, Clone)]
+pub struct ChunkRng(u64);
+
+impl ChunkRng {
+    pub fn new(seed: u64, chunk_x: usize, chunk_z: usize) -> Self {
+        let mut rng = Self(
+            seed ^ (chunk_x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (chunk_z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+        );
+        // Mixing a few times so neighbouring chunks get unrelated sequences
+        rng.next_u64();
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A random integer in `[min, max)`
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min).max(1) as u64) as i32
+    }
+
+    /// Returns true with a probability of `probability`
+    pub fn chance(&mut self, probability: f32) -> bool {
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) < probability
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeKind {
+    Oak,
+    Birch,
+    Pine,
+    Jungle,
+}
+
+impl TreeKind {
+    fn log(&self) -> CubeTypes {
+        match self {
+            TreeKind::Oak => CubeTypes::OakLog,
+            TreeKind::Birch => CubeTypes::BirchLog,
+            TreeKind::Pine => CubeTypes::SpruceLog,
+            TreeKind::Jungle => CubeTypes::JungleLog,
+        }
+    }
+}
+
+/// Writes the voxels of the features into the world. Features are placed from the chunk they
+/// grow in, but written with world positions: as the whole terrain is generated before the
+/// decoration, a tree growing on a chunk border simply spills over into the neighbouring chunk.
+struct Decorator<'a> {
+    voxels: &'a mut [Voxel],
+    // The height of the dirt on top of each column of the undecorated terrain, X first, so the
+    // features already grown never change where the next ones go
+    ground: Vec<Option<i32>>,
+}
+
+impl<'a> Decorator<'a> {
+    fn new(voxels: &'a mut [Voxel]) -> Self {
+        let mut decorator = Self {
+            voxels,
+            ground: Vec::new(),
+        };
+        let (width, depth) = ((WORLD_W * CHUNK_SIZE) as i32, (WORLD_D * CHUNK_SIZE) as i32);
+        decorator.ground = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                decorator
+                    .surface(x, z)
+                    .filter(|surface| decorator.get(*surface) == CubeTypes::Dirt)
+                    .map(|surface| surface.y)
+            })
+            .collect();
+        decorator
+    }
+
+    fn get(&self, position: IVec3) -> CubeTypes {
+        match VxWorldCoord::from_position(position) {
+            Some(world_coord) => self.voxels[world_coord.get_id()].cube_type,
+            None => CubeTypes::Empty,
+        }
+    }
+
+    fn set(&mut self, position: IVec3, voxel: Voxel) {
+        if let Some(world_coord) = VxWorldCoord::from_position(position) {
+            self.voxels[world_coord.get_id()] = voxel;
+        }
+    }
+
+    /// Only fills empty voxels, so leaves and flowers never replace the terrain or a trunk
+    fn fill(&mut self, position: IVec3, cube_type: CubeTypes) {
+        if self.get(position) == CubeTypes::Empty {
+            self.set(position, cube_type.into());
+        }
+    }
+
+    /// The position of the top most non empty voxel of a column
+    fn surface(&self, x: i32, z: i32) -> Option<IVec3> {
+        (0..(WORLD_H * CHUNK_SIZE) as i32)
+            .rev()
+            .map(|y| IVec3::new(x, y, z))
+            .find(|position| self.get(*position) != CubeTypes::Empty)
+    }
+
+    /// The surface of a column of the undecorated terrain, if it is made of dirt
+    fn ground(&self, x: i32, z: i32) -> Option<IVec3> {
+        let width = (WORLD_W * CHUNK_SIZE) as i32;
+        if x < 0 || z < 0 || x >= width || z >= (WORLD_D * CHUNK_SIZE) as i32 {
+            return None;
+        }
+        let y = self.ground[(x + z * width) as usize]?;
+        Some(IVec3::new(x, y, z))
+    }
+
+    /// Picks a random column of the chunk starting at `origin`, and returns its ground if any
+    fn random_ground(&self, origin: IVec3, rng: &mut ChunkRng) -> Option<IVec3> {
+        let x = origin.x + rng.range(0, CHUNK_SIZE as i32);
+        let z = origin.z + rng.range(0, CHUNK_SIZE as i32);
+        self.ground(x, z)
+    }
+
+    fn trunk(&mut self, base: IVec3, height: i32, log: CubeTypes) {
+        let voxel = Voxel::new(log, BlockState::default().with_axis(Axis::Y));
+        for y in 0..height {
+            let position = base + IVec3::Y * y;
+            if matches!(self.get(position), CubeTypes::Empty | CubeTypes::Leaves) {
+                self.set(position, voxel);
+            }
+        }
+    }
+
+    /// A horizontal disc of leaves, its corners being randomly trimmed
+    fn leaves_layer(&mut self, center: IVec3, radius: i32, rng: &mut ChunkRng) {
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let corner = x.abs() == radius && z.abs() == radius;
+                if x * x + z * z > radius * radius + 1 || (corner && rng.chance(0.5)) {
+                    continue;
+                }
+                self.fill(center + IVec3::new(x, 0, z), CubeTypes::Leaves);
+            }
+        }
+    }
+
+    fn tree(&mut self, base: IVec3, kind: TreeKind, rng: &mut ChunkRng) {
+        match kind {
+            TreeKind::Oak | TreeKind::Birch => {
+                let height = match kind {
+                    TreeKind::Oak => rng.range(4, 7),
+                    _ => rng.range(5, 8),
+                };
+                let top = base + IVec3::Y * height;
+                self.leaves_layer(top - IVec3::Y * 2, 2, rng);
+                self.leaves_layer(top - IVec3::Y, 2, rng);
+                self.leaves_layer(top, 1, rng);
+                self.leaves_layer(top + IVec3::Y, 1, rng);
+                self.trunk(base, height, kind.log());
+            }
+            TreeKind::Pine => {
+                let height = rng.range(7, 11);
+                // Cone shaped, the layers shrinking toward the tip
+                let mut radius = 0;
+                for y in (2..=height).rev() {
+                    self.leaves_layer(base + IVec3::Y * y, radius, rng);
+                    radius = if radius >= 3 { 1 } else { radius + 1 };
+                }
+                self.fill(base + IVec3::Y * (height + 1), CubeTypes::Leaves);
+                self.trunk(base, height, kind.log());
+            }
+            TreeKind::Jungle => {
+                let height = rng.range(10, 16);
+                let top = base + IVec3::Y * height;
+                for y in -2..=1 {
+                    let radius = if y < 0 { 4 } else { 3 - y };
+                    self.leaves_layer(top + IVec3::Y * y, radius, rng);
+                }
+                // Jungle trees have a two by two trunk
+                for offset in [IVec3::ZERO, IVec3::X, IVec3::Z, IVec3::X + IVec3::Z] {
+                    self.trunk(base + offset, height, kind.log());
+                }
+            }
+        }
+    }
+
+    fn boulder(&mut self, center: IVec3, rng: &mut ChunkRng) {
+        let radius = rng.range(1, 3);
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                for z in -radius..=radius {
+                    if x * x + y * y + z * z > radius * radius + rng.range(0, 2) {
+                        continue;
+                    }
+                    let cube_type = if rng.chance(0.3) {
+                        CubeTypes::MossyCobblestone
+                    } else {
+                        CubeTypes::Cobblestone
+                    };
+                    self.set(center + IVec3::new(x, y, z), cube_type.into());
+                }
+            }
+        }
+    }
+
+    fn flowers(&mut self, center: IVec3, rng: &mut ChunkRng) {
+        let flower = if rng.chance(0.5) {
+            CubeTypes::Poppy
+        } else {
+            CubeTypes::Dandelion
+        };
+        for _ in 0..rng.range(3, 8) {
+            let (x, z) = (center.x + rng.range(-3, 4), center.z + rng.range(-3, 4));
+            if let Some(ground) = self.ground(x, z) {
+                self.fill(ground + IVec3::Y, flower);
+            }
+        }
+    }
+}
+
+/// Decorates the bare terrain with trees, boulders and flowers. The placement only depends on
+/// the seed and the terrain, so the same world is decorated the same way every time.
+pub fn decorate(voxels: &mut [Voxel], seed: u64) {
+    let mut decorator = Decorator::new(voxels);
+    for c_x in 0..WORLD_W {
+        for c_z in 0..WORLD_D {
+            let mut rng = ChunkRng::new(seed, c_x, c_z);
+            let origin = IVec3::new((c_x * CHUNK_SIZE) as i32, 0, (c_z * CHUNK_SIZE) as i32);
+
+            for _ in 0..rng.range(0, 4) {
+                let kind = match rng.range(0, 10) {
+                    0..=3 => TreeKind::Oak,
+                    4..=5 => TreeKind::Birch,
+                    6..=8 => TreeKind::Pine,
+                    _ => TreeKind::Jungle,
+                };
+                if let Some(ground) = decorator.random_ground(origin, &mut rng) {
+                    decorator.tree(ground + IVec3::Y, kind, &mut rng);
+                }
+            }
+            if rng.chance(0.25) {
+                if let Some(ground) = decorator.random_ground(origin, &mut rng) {
+                    decorator.boulder(ground, &mut rng);
+                }
+            }
+            for _ in 0..rng.range(0, 3) {
+                if let Some(ground) = decorator.random_ground(origin, &mut rng) {
+                    decorator.flowers(ground, &mut rng);
+                }
+            }
+        }
+    }
+}
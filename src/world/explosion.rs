@@ -0,0 +1,256 @@
+//! Explosions, destroying the blocks around a point. Rays are cast outward from the center, each
+//! one carrying the power of the explosion and losing some of it at each step, and more through
+//! the blocks resisting the blast. The blocks reached while a ray still has power left are
+//! destroyed, all of them in a single edit.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use super::{ChunkRng, CubeTypes, Voxel, VxWorld, WORLD_SEED};
+
+/// Rays cast along each edge of the cube of directions
+const RAYS_PER_EDGE: i32 = 16;
+/// Distance, in blocks, between the points a ray goes through
+const RAY_STEP: f32 = 0.3;
+/// Power lost by a ray at each step, through the air
+const AIR_ATTENUATION: f32 = 0.225;
+/// The strongest explosion, larger powers being brought down to it
+const MAX_POWER: f32 = 64.0;
+
+impl CubeTypes {
+    /// How much a block weakens the rays of an explosion going through it
+    pub fn blast_resistance(&self) -> f32 {
+        match self {
+            CubeTypes::Empty | CubeTypes::Poppy | CubeTypes::Dandelion | CubeTypes::Wheat => 0.0,
+            CubeTypes::Leaves => 0.2,
+            CubeTypes::Dirt | CubeTypes::BareDirt | CubeTypes::Sand | CubeTypes::Ice => 0.5,
+            CubeTypes::Gravel => 0.6,
+            CubeTypes::Sign => 1.0,
+            CubeTypes::OakLog
+            | CubeTypes::BirchLog
+            | CubeTypes::SpruceLog
+            | CubeTypes::JungleLog
+            | CubeTypes::Chest => 2.5,
+            CubeTypes::Stone
+            | CubeTypes::Cobblestone
+            | CubeTypes::MossyCobblestone
+            | CubeTypes::CoalOre
+            | CubeTypes::IronOre
+            | CubeTypes::GoldOre
+            | CubeTypes::DiamondOre
+            | CubeTypes::Deepslate
+            | CubeTypes::Furnace => 6.0,
+            // Fluids swallow the blast without being destroyed
+            CubeTypes::Water | CubeTypes::Lava => 100.0,
+        }
+    }
+}
+
+/// Asks for an explosion of `power` at `center`
+#[derive(Debug, Clone, Copy, Event)]
+pub struct Explosion {
+    pub center: Vec3,
+    pub power: f32,
+}
+
+/// Sent once an explosion went off, with the voxels it destroyed as they were before
+#[derive(Debug, Clone, Event)]
+pub struct Exploded {
+    pub center: Vec3,
+    pub destroyed: Vec<(IVec3, Voxel)>,
+}
+
+impl VxWorld {
+    /// Blows up the blocks around `center`, the further the greater `power` is, up to a power of
+    /// 64, and returns the voxels destroyed as they were before. The rays are randomized from the
+    /// center only, so the same explosion in the same world always destroys the same blocks.
+    pub fn explode(&mut self, center: Vec3, power: f32) -> Vec<(IVec3, Voxel)> {
+        let destroyed = blast(center, power, |position| self.get_voxel(position));
+        self.edit_voxels(
+            destroyed
+                .iter()
+                .map(|(position, _)| (*position, Voxel::default())),
+        );
+        destroyed
+    }
+}
+
+// The voxels an explosion destroys, sorted by position, without changing anything
+fn blast(center: Vec3, power: f32, get_voxel: impl Fn(IVec3) -> Voxel) -> Vec<(IVec3, Voxel)> {
+    // An infinite power would send the rays on forever
+    let power = power.clamp(0.0, MAX_POWER);
+    let cell = center.round().as_ivec3();
+    let mut rng = ChunkRng::new(WORLD_SEED ^ cell.y as u64, cell.x as usize, cell.z as usize);
+    let mut reached = HashSet::new();
+    let last = RAYS_PER_EDGE - 1;
+    for x in 0..RAYS_PER_EDGE {
+        for y in 0..RAYS_PER_EDGE {
+            for z in 0..RAYS_PER_EDGE {
+                // Only the rays pointing at the surface of the cube of directions
+                if ![x, y, z].iter().any(|&i| i == 0 || i == last) {
+                    continue;
+                }
+                let direction =
+                    (IVec3::new(x, y, z).as_vec3() / last as f32 * 2.0 - Vec3::ONE).normalize();
+                // Between 70% and 130% of the power
+                let mut intensity = power * (0.7 + rng.range(0, 61) as f32 / 100.0);
+                let mut point = center;
+                while intensity > 0.0 {
+                    let position = point.round().as_ivec3();
+                    let cube_type = get_voxel(position).cube_type;
+                    if cube_type != CubeTypes::Empty {
+                        intensity -= (cube_type.blast_resistance() + 0.3) * RAY_STEP;
+                        if intensity > 0.0 && !cube_type.is_fluid() {
+                            reached.insert(position);
+                        }
+                    }
+                    point += direction * RAY_STEP;
+                    intensity -= AIR_ATTENUATION;
+                }
+            }
+        }
+    }
+    let mut reached: Vec<IVec3> = reached.into_iter().collect();
+    reached.sort_by_key(|position| (position.x, position.y, position.z));
+    reached
+        .into_iter()
+        .map(|position| (position, get_voxel(position)))
+        .collect()
+}
+
+/// Sets off the explosions asked for. The chunks they touched are remeshed once, along with the
+/// other edits of the frame.
+pub fn explode(
+    mut explosions: EventReader<Explosion>,
+    mut exploded: EventWriter<Exploded>,
+    mut my_world: ResMut<VxWorld>,
+) {
+    for explosion in explosions.read() {
+        let destroyed = my_world.explode(explosion.center, explosion.power);
+        if !destroyed.is_empty() {
+            exploded.write(Exploded {
+                center: explosion.center,
+                destroyed,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{world::chunk_id, CHUNK_SIZE, CHUNK_VOLUME, WORLD_VOL};
+
+    // A world of air holding the given voxels
+    fn layout(voxels: &[(IVec3, CubeTypes)]) -> impl Fn(IVec3) -> Voxel {
+        let voxels: HashMap<IVec3, Voxel> = voxels
+            .iter()
+            .map(|(position, cube_type)| (*position, (*cube_type).into()))
+            .collect();
+        move |position| voxels.get(&position).copied().unwrap_or_default()
+    }
+
+    fn cube(center: IVec3, radius: i32, cube_type: CubeTypes) -> Vec<(IVec3, CubeTypes)> {
+        let range = -radius..=radius;
+        range
+            .clone()
+            .flat_map(|x| range.clone().map(move |y| (x, y)))
+            .flat_map(|(x, y)| range.clone().map(move |z| IVec3::new(x, y, z)))
+            .map(|offset| (center + offset, cube_type))
+            .collect()
+    }
+
+    #[test]
+    fn destroys_the_blocks_in_range() {
+        let mut voxels = cube(IVec3::ZERO, 1, CubeTypes::Dirt);
+        voxels.push((IVec3::new(20, 0, 0), CubeTypes::Dirt));
+        let get_voxel = layout(&voxels);
+        let destroyed = blast(Vec3::ZERO, 6.0, &get_voxel);
+        let positions: Vec<IVec3> = destroyed.iter().map(|(position, _)| *position).collect();
+        let mut expected: Vec<IVec3> = cube(IVec3::ZERO, 1, CubeTypes::Dirt)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+        expected.sort_by_key(|position| (position.x, position.y, position.z));
+        assert_eq!(positions, expected);
+        assert!(destroyed
+            .iter()
+            .all(|(_, voxel)| voxel.cube_type == CubeTypes::Dirt));
+        // The same explosion always destroys the same blocks
+        assert_eq!(blast(Vec3::ZERO, 6.0, &get_voxel), destroyed);
+    }
+
+    #[test]
+    fn resistant_blocks_shield_the_blocks_behind() {
+        let target = IVec3::new(4, 0, 0);
+        let open = layout(&[(target, CubeTypes::Dirt)]);
+        assert!(blast(Vec3::ZERO, 8.0, open)
+            .iter()
+            .any(|(position, _)| *position == target));
+        for shield in [CubeTypes::Water, CubeTypes::Stone] {
+            // A wall two blocks thick between the center and the target
+            let mut voxels: Vec<(IVec3, CubeTypes)> = (2..=3)
+                .flat_map(|x| (-6..=6).map(move |y| (x, y)))
+                .flat_map(|(x, y)| (-6..=6).map(move |z| (IVec3::new(x, y, z), shield)))
+                .collect();
+            voxels.push((target, CubeTypes::Dirt));
+            let destroyed = blast(Vec3::ZERO, 8.0, layout(&voxels));
+            assert!(
+                destroyed.iter().all(|(position, _)| *position != target),
+                "{shield:?}"
+            );
+            // Fluids are never destroyed
+            if shield.is_fluid() {
+                assert!(destroyed.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn infinite_power_ends() {
+        let get_voxel = layout(&cube(IVec3::ZERO, 2, CubeTypes::Stone));
+        assert_eq!(blast(Vec3::ZERO, f32::INFINITY, &get_voxel).len(), 125);
+        assert!(blast(Vec3::ZERO, f32::NAN, &get_voxel).is_empty());
+        assert!(blast(Vec3::ZERO, -1.0, &get_voxel).is_empty());
+    }
+
+    #[test]
+    fn flags_each_chunk_once() {
+        let mut my_world = VxWorld::from_voxels(vec![Voxel::default(); WORLD_VOL * CHUNK_VOLUME]);
+        // Dirt across the edge between four chunks
+        let corner = IVec3::new(64, 40, 64);
+        my_world.set_voxels(
+            cube(corner, 2, CubeTypes::Dirt)
+                .into_iter()
+                .map(|(position, cube_type)| (position, cube_type.into())),
+        );
+        my_world.dirty_chunks.clear();
+        let destroyed = my_world.explode(corner.as_vec3(), 12.0);
+        assert_eq!(destroyed.len(), 125);
+        assert!(destroyed
+            .iter()
+            .all(|(position, _)| my_world.get_voxel(*position) == Voxel::default()));
+        // The four chunks around the edge and their neighbours, each one flagged once
+        let mut expected = HashSet::new();
+        for (position, _) in &destroyed {
+            let chunk = position.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let neighbour = (chunk + IVec3::new(x, y, z)).as_uvec3();
+                        expected.extend(chunk_id((
+                            neighbour.x as usize,
+                            neighbour.y as usize,
+                            neighbour.z as usize,
+                        )));
+                    }
+                }
+            }
+        }
+        assert_eq!(my_world.dirty_chunks, expected);
+        assert_eq!(my_world.dirty_chunks.len(), 4 * 4 * 2);
+    }
+}
@@ -0,0 +1,209 @@
+//! Blocks subject to gravity, such as sand and gravel. A block left without anything below it
+//! leaves the grid and falls as an entity, going back into the grid where it lands, or dropping as
+//! an item if what it lands on is not a full block.
+
+use std::collections::HashMap;
+
+use bevy::{
+    image::{ImageLoaderSettings, ImageSampler},
+    prelude::*,
+    render::mesh::VertexAttributeValues,
+};
+
+use super::{chunk::face_tile, CubeTypes, FaceType, Voxel, VxWorld};
+
+// In blocks per second, and blocks per second squared
+const GRAVITY: f32 = 32.0;
+const TERMINAL_SPEED: f32 = 40.0;
+/// Falling blocks below this height fell out of the world
+const WORLD_BOTTOM: f32 = -64.0;
+/// Size of a dropped item relatively to a block
+const ITEM_SCALE: f32 = 0.25;
+/// Radians a dropped item turns each second
+const ITEM_SPIN: f32 = 1.5;
+
+impl CubeTypes {
+    /// Whether the cube falls when nothing holds it up
+    pub fn has_gravity(&self) -> bool {
+        matches!(self, CubeTypes::Sand | CubeTypes::Gravel)
+    }
+}
+
+/// A block falling out of the grid
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FallingBlock {
+    pub voxel: Voxel,
+    /// The upward speed, in blocks per second
+    pub velocity: f32,
+}
+
+/// A block lying on the ground, waiting to be picked up
+#[derive(Debug, Clone, Copy, Component)]
+pub struct ItemDrop(pub CubeTypes);
+
+/// The meshes of single cubes, textured like the cubes of the chunks, and their material
+#[derive(Debug, Resource)]
+pub struct CubeMeshes {
+    material: Handle<StandardMaterial>,
+    meshes: HashMap<CubeTypes, Handle<Mesh>>,
+}
+
+impl CubeMeshes {
+    pub fn material(&self) -> Handle<StandardMaterial> {
+        self.material.clone()
+    }
+
+    /// The mesh of a cube type, built the first time it is asked for
+    pub fn mesh(&mut self, cube_type: CubeTypes, meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+        self.meshes
+            .entry(cube_type)
+            .or_insert_with(|| meshes.add(cube_mesh(cube_type)))
+            .clone()
+    }
+}
+
+// A cube of a block wide, each face mapped onto its tile of the atlas
+fn cube_mesh(cube_type: CubeTypes) -> Mesh {
+    let mut mesh = Mesh::from(Cuboid::from_length(1.0));
+    let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute(Mesh::ATTRIBUTE_NORMAL).cloned()
+    else {
+        return mesh;
+    };
+    if let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+        for (uv, normal) in uvs.iter_mut().zip(normals) {
+            let face = match normal.map(|coord| coord as i8) {
+                [0, 1, 0] => FaceType::Top,
+                [0, -1, 0] => FaceType::Bottom,
+                [1, 0, 0] => FaceType::Right,
+                [-1, 0, 0] => FaceType::Left,
+                [0, 0, 1] => FaceType::Back,
+                _ => FaceType::Front,
+            };
+            // The blocks subject to gravity are not laid sideways, the turns are left out
+            let (row, column, _) = face_tile(cube_type, &face).unwrap_or_default();
+            *uv = [(column as f32 + uv[0]) / 32.0, (row as f32 + uv[1]) / 32.0];
+        }
+    }
+    mesh
+}
+
+pub fn init_cube_meshes(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color_texture: Some(asset_server.load_with_settings(
+            "textures.png",
+            |settings: &mut ImageLoaderSettings| {
+                settings.sampler = ImageSampler::nearest();
+            },
+        )),
+        unlit: true,
+        ..default()
+    });
+    commands.insert_resource(CubeMeshes {
+        material,
+        meshes: HashMap::new(),
+    });
+}
+
+/// Spawns a dropped item lying on the bottom of the voxel at `position`
+pub fn spawn_item_drop(
+    commands: &mut Commands,
+    cube_meshes: &mut CubeMeshes,
+    meshes: &mut Assets<Mesh>,
+    cube_type: CubeTypes,
+    position: IVec3,
+) {
+    let bottom = position.as_vec3() - Vec3::Y * (0.5 - ITEM_SCALE / 2.0);
+    commands.spawn((
+        ItemDrop(cube_type),
+        Mesh3d(cube_meshes.mesh(cube_type, meshes)),
+        MeshMaterial3d(cube_meshes.material()),
+        Transform::from_translation(bottom).with_scale(Vec3::splat(ITEM_SCALE)),
+    ));
+}
+
+/// Takes the blocks left without anything below them out of the grid, as falling entities
+pub fn start_falling(
+    mut commands: Commands,
+    mut my_world: ResMut<VxWorld>,
+    mut cube_meshes: ResMut<CubeMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for position in my_world.take_unsettled() {
+        let voxel = my_world.get_voxel(position);
+        let below = my_world.get_voxel(position - IVec3::Y).cube_type;
+        if !voxel.cube_type.has_gravity() || (below != CubeTypes::Empty && !below.is_fluid()) {
+            continue;
+        }
+        // The block above gets unsettled in turn
+        my_world.set_voxel(position, Voxel::default());
+        commands.spawn((
+            FallingBlock {
+                voxel,
+                velocity: 0.0,
+            },
+            Mesh3d(cube_meshes.mesh(voxel.cube_type, &mut meshes)),
+            MeshMaterial3d(cube_meshes.material()),
+            Transform::from_translation(position.as_vec3()),
+        ));
+    }
+}
+
+/// Moves the falling blocks down, and puts them back into the grid when they meet a block
+pub fn fall(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut my_world: ResMut<VxWorld>,
+    mut cube_meshes: ResMut<CubeMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut falling_blocks: Query<(Entity, &mut FallingBlock, &mut Transform)>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut falling_block, mut transform) in &mut falling_blocks {
+        falling_block.velocity = (falling_block.velocity - GRAVITY * delta).max(-TERMINAL_SPEED);
+        let from = transform.translation.y;
+        let to = from + falling_block.velocity * delta;
+        if to < WORLD_BOTTOM {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // The voxels the bottom of the block went into, from the highest, so none is skipped
+        let column = transform.translation.round().as_ivec3();
+        let hit = (to.floor() as i32..=from.floor() as i32)
+            .rev()
+            .map(|y| IVec3::new(column.x, y, column.z))
+            .find(|position| {
+                let cube_type = my_world.get_voxel(*position).cube_type;
+                cube_type != CubeTypes::Empty && !cube_type.is_fluid()
+            });
+        let Some(hit) = hit else {
+            transform.translation.y = to;
+            continue;
+        };
+        commands.entity(entity).despawn();
+        let landing = hit + IVec3::Y;
+        let replaced = my_world.get_voxel(landing).cube_type;
+        let fits = replaced == CubeTypes::Empty || replaced.is_fluid();
+        if my_world.get_voxel(hit).cube_type.is_solid() && fits {
+            my_world.set_voxel(landing, falling_block.voxel);
+        } else {
+            spawn_item_drop(
+                &mut commands,
+                &mut cube_meshes,
+                &mut meshes,
+                falling_block.voxel.cube_type,
+                landing,
+            );
+        }
+    }
+}
+
+pub fn spin_item_drops(time: Res<Time>, mut items: Query<&mut Transform, With<ItemDrop>>) {
+    for mut transform in &mut items {
+        transform.rotate_y(ITEM_SPIN * time.delta_secs());
+    }
+}
@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use image::{
+    error::{LimitError, LimitErrorKind},
+    ImageError, ImageResult,
+};
+use noisy_bevy::simplex_noise_2d;
+
+use super::{CubeTypes, CHUNK_SIZE};
+
+/// The fewest voxels a pixel can cover, smaller horizontal scales being raised to it
+const MIN_HORIZONTAL_SCALE: f32 = 0.01;
+
+/// How an image is sampled beyond its bounds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EdgeMode {
+    /// The image repeats itself
+    #[default]
+    Tile,
+    /// The pixels of the border stretch out forever
+    Clamp,
+}
+
+/// Where the height of each column of the terrain comes from
+#[derive(Debug, Clone, Default, Resource)]
+pub enum TerrainGenerator {
+    /// Rolling hills from a simplex noise
+    #[default]
+    Noise,
+    /// The pixels of a heightmap image
+    Heightmap(Heightmap),
+}
+
+impl TerrainGenerator {
+    /// The height of the terrain at a column of the world: the voxels below it are filled
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        match self {
+            TerrainGenerator::Noise => {
+                (CHUNK_SIZE as f32) * (simplex_noise_2d(0.01 * Vec2::new(x, z)) + 1.0)
+            }
+            TerrainGenerator::Heightmap(heightmap) => heightmap.height(x, z),
+        }
+    }
+
+    /// The cube type forced on the surface voxel of a column, if any
+    pub fn surface_block(&self, x: f32, z: f32) -> Option<CubeTypes> {
+        match self {
+            TerrainGenerator::Noise => None,
+            TerrainGenerator::Heightmap(heightmap) => heightmap.surface_block(x, z),
+        }
+    }
+}
+
+/// A terrain read from a grayscale image, black being the lowest and white the highest height
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    // Normalized between 0 and 1
+    values: Vec<f32>,
+    /// Number of voxels covered by a pixel, along X and Z, at least 0.01
+    pub horizontal_scale: f32,
+    /// Number of voxels between a black and a white pixel
+    pub vertical_scale: f32,
+    /// The height of a black pixel
+    pub base_height: f32,
+    pub edges: EdgeMode,
+    /// Picks the surface block of each column
+    pub splat_map: Option<SplatMap>,
+}
+
+// Images without any pixel have nothing to sample
+fn check_size(width: u32, height: u32) -> ImageResult<()> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    }
+    Ok(())
+}
+
+// Finds the pixel covering a coordinate along one axis of the image
+fn pixel(coordinate: i64, size: u32, edges: EdgeMode) -> usize {
+    match edges {
+        EdgeMode::Tile => coordinate.rem_euclid(size as i64) as usize,
+        EdgeMode::Clamp => coordinate.clamp(0, size as i64 - 1) as usize,
+    }
+}
+
+impl Heightmap {
+    fn new(width: u32, height: u32, values: Vec<f32>) -> Self {
+        Self {
+            width,
+            height,
+            values,
+            horizontal_scale: 1.0,
+            vertical_scale: 48.0,
+            base_height: 1.0,
+            edges: EdgeMode::default(),
+            splat_map: None,
+        }
+    }
+
+    /// Reads a grayscale image, 8 or 16 bits per pixel. Colour images are converted to their
+    /// luminance. Empty images are refused.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let image = image::open(path)?.into_luma16();
+        check_size(image.width(), image.height())?;
+        let values = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect();
+        Ok(Self::new(image.width(), image.height(), values))
+    }
+
+    /// Reads a raw 16 bits heightmap (`.r16`), as exported by most terrain tools: little endian
+    /// values, one row after the other. Returns None if the size of the data does not match.
+    pub fn from_raw16(bytes: &[u8], width: u32) -> Option<Self> {
+        let pixels = bytes.len() / 2;
+        if pixels == 0
+            || width == 0
+            || !bytes.len().is_multiple_of(2)
+            || !pixels.is_multiple_of(width as usize)
+        {
+            return None;
+        }
+        let values = bytes
+            .chunks_exact(2)
+            .map(|value| u16::from_le_bytes([value[0], value[1]]) as f32 / u16::MAX as f32)
+            .collect();
+        Some(Self::new(width, (pixels / width as usize) as u32, values))
+    }
+
+    fn value(&self, x: i64, z: i64) -> f32 {
+        let (x, z) = (
+            pixel(x, self.width, self.edges),
+            pixel(z, self.height, self.edges),
+        );
+        self.values[x + z * self.width as usize]
+    }
+
+    // The position of a column on the image, in pixels
+    fn image_position(&self, x: f32, z: f32) -> Vec2 {
+        Vec2::new(x, z) / self.horizontal_scale.max(MIN_HORIZONTAL_SCALE)
+    }
+
+    /// The height of a column, interpolated between the four nearest pixels so scaled up images
+    /// give smooth slopes
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let position = self.image_position(x, z);
+        let (origin, t) = (position.floor(), position.fract_gl());
+        let (x, z) = (origin.x as i64, origin.y as i64);
+        let top = self.value(x, z).lerp(self.value(x + 1, z), t.x);
+        let bottom = self.value(x, z + 1).lerp(self.value(x + 1, z + 1), t.x);
+        self.base_height + self.vertical_scale * top.lerp(bottom, t.y)
+    }
+
+    fn surface_block(&self, x: f32, z: f32) -> Option<CubeTypes> {
+        let splat_map = self.splat_map.as_ref()?;
+        let position = self.image_position(x, z).floor();
+        splat_map.cube_type(position.x as i64, position.y as i64, self.edges)
+    }
+}
+
+/// A colour image laid over the heightmap, each pixel choosing the block of the surface it
+/// covers: the block whose colour is the closest in the table
+#[derive(Debug, Clone)]
+pub struct SplatMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 3]>,
+    pub colors: Vec<([u8; 3], CubeTypes)>,
+}
+
+impl SplatMap {
+    /// Reads a colour image, refusing empty ones
+    pub fn load(path: impl AsRef<Path>, colors: Vec<([u8; 3], CubeTypes)>) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb8();
+        check_size(image.width(), image.height())?;
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|pixel| pixel.0).collect(),
+            colors,
+        })
+    }
+
+    fn cube_type(&self, x: i64, z: i64, edges: EdgeMode) -> Option<CubeTypes> {
+        let color = self.pixels
+            [pixel(x, self.width, edges) + pixel(z, self.height, edges) * self.width as usize];
+        self.colors
+            .iter()
+            .min_by_key(|(reference, _)| {
+                reference
+                    .iter()
+                    .zip(color)
+                    .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|(_, cube_type)| *cube_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 2x2 heightmap, from black to white
+    fn heightmap() -> Heightmap {
+        let bytes: Vec<u8> = [0u16, u16::MAX / 3, u16::MAX / 3 * 2, u16::MAX]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        Heightmap::from_raw16(&bytes, 2).unwrap()
+    }
+
+    #[test]
+    fn raw_heightmaps_must_fill_their_rows() {
+        assert!(Heightmap::from_raw16(&[], 1).is_none());
+        assert!(Heightmap::from_raw16(&[0; 8], 0).is_none());
+        assert!(Heightmap::from_raw16(&[0; 7], 1).is_none());
+        assert!(Heightmap::from_raw16(&[0; 8], 3).is_none());
+        let heightmap = heightmap();
+        assert_eq!((heightmap.width, heightmap.height), (2, 2));
+    }
+
+    #[test]
+    fn empty_images_are_refused() {
+        assert!(check_size(0, 4).is_err());
+        assert!(check_size(4, 0).is_err());
+        assert!(check_size(1, 1).is_ok());
+    }
+
+    #[test]
+    fn heights_are_interpolated_between_pixels() {
+        let mut heightmap = heightmap();
+        heightmap.base_height = 0.0;
+        heightmap.vertical_scale = 3.0;
+        assert_eq!(heightmap.height(0.0, 0.0), 0.0);
+        assert_eq!(heightmap.height(1.0, 1.0), 3.0);
+        assert!((heightmap.height(0.5, 0.0) - 0.5).abs() < 1e-4);
+        // Tiled
+        assert_eq!(heightmap.height(2.0, 2.0), 0.0);
+        heightmap.edges = EdgeMode::Clamp;
+        assert_eq!(heightmap.height(-5.0, 7.0), heightmap.height(0.0, 1.0));
+    }
+
+    #[test]
+    fn non_positive_scales_give_finite_heights() {
+        let mut heightmap = heightmap();
+        for scale in [0.0, -2.0, f32::NAN] {
+            heightmap.horizontal_scale = scale;
+            assert!(heightmap.height(10.0, -3.0).is_finite());
+        }
+    }
+}
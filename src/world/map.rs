@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use image::{Rgba, RgbaImage};
+
+use super::{CubeTypes, Voxel, VxWorld, VxWorldCoord, CHUNK_SIZE, WORLD_H};
+
+/// What the pixels of a map show
+#[derive(Clone, Copy)]
+pub enum MapMode<'a> {
+    /// The colour of the top most block of each column, shaded by its height
+    Surface,
+    /// The colour given by a biome lookup for each column, shaded by its height. The world does
+    /// not store biomes, so they come from the caller, from an imported region for instance.
+    Biome(&'a dyn Fn(i32, i32) -> [u8; 3]),
+    /// A horizontal cut of the world at a given height: the solid blocks in their colour, the
+    /// caves and the open air in black
+    CaveSlice(i32),
+}
+
+// Colour of the columns without any block
+const VOID_COLOR: [u8; 4] = [0, 0, 0, 0];
+
+fn voxel_at(voxels: &[Voxel], position: IVec3) -> Voxel {
+    match VxWorldCoord::from_position(position) {
+        Some(world_coord) => voxels[world_coord.get_id()],
+        None => Voxel::default(),
+    }
+}
+
+/// The height of the top most non empty voxel of a column
+pub fn column_height(voxels: &[Voxel], x: i32, z: i32) -> Option<i32> {
+    (0..(WORLD_H * CHUNK_SIZE) as i32)
+        .rev()
+        .find(|y| voxel_at(voxels, IVec3::new(x, *y, z)).cube_type != CubeTypes::Empty)
+}
+
+// Brightens or darkens a colour
+fn shade(color: [u8; 3], factor: f32) -> [u8; 4] {
+    let [r, g, b] = color.map(|channel| (channel as f32 * factor).clamp(0.0, 255.0) as u8);
+    [r, g, b, 255]
+}
+
+/// The colour of a column seen from above: the colour of its top most block, darker the lower it
+/// is, and lit like a relief on the slopes facing north
+pub fn column_color(voxels: &[Voxel], x: i32, z: i32, mode: &MapMode) -> [u8; 4] {
+    if let MapMode::CaveSlice(y) = mode {
+        let cube_type = voxel_at(voxels, IVec3::new(x, *y, z)).cube_type;
+        return match cube_type {
+            CubeTypes::Empty => [0, 0, 0, 255],
+            _ => shade(cube_type.color(), 1.0),
+        };
+    }
+    let Some(height) = column_height(voxels, x, z) else {
+        return VOID_COLOR;
+    };
+    let color = match mode {
+        MapMode::Biome(biome) => biome(x, z),
+        _ => voxel_at(voxels, IVec3::new(x, height, z)).cube_type.color(),
+    };
+    let top = (WORLD_H * CHUNK_SIZE) as f32;
+    let mut factor = 0.6 + 0.6 * height as f32 / top;
+    // Comparing with the column to the north, as Minecraft maps do
+    if let Some(north) = column_height(voxels, x, z - 1) {
+        factor *= match height.cmp(&north) {
+            std::cmp::Ordering::Greater => 1.15,
+            std::cmp::Ordering::Less => 0.85,
+            std::cmp::Ordering::Equal => 1.0,
+        };
+    }
+    shade(color, factor)
+}
+
+/// Renders an overview of the columns between two corners (X, Z) of the world, both included,
+/// one pixel per column, north being up. Only needs the voxels, so it can run without a window.
+pub fn render_map(voxels: &[Voxel], corner_a: IVec2, corner_b: IVec2, mode: &MapMode) -> RgbaImage {
+    let (min, max) = (corner_a.min(corner_b), corner_a.max(corner_b));
+    let size = (max - min + IVec2::ONE).as_uvec2();
+    RgbaImage::from_fn(size.x, size.y, |x, y| {
+        Rgba(column_color(
+            voxels,
+            min.x + x as i32,
+            min.y + y as i32,
+            mode,
+        ))
+    })
+}
+
+impl VxWorld {
+    /// Renders an overview of a region of the world, see [render_map]
+    pub fn render_map(&self, corner_a: IVec2, corner_b: IVec2, mode: &MapMode) -> RgbaImage {
+        render_map(&self.voxels, corner_a, corner_b, mode)
+    }
+}
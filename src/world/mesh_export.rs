@@ -0,0 +1,346 @@
+//! Export of the chunk meshes to formats other tools understand: glTF 2.0 (as a single binary
+//! `.glb` file) and Wavefront OBJ as a fallback.
+//!
+//! Our meshes carry custom `VxType`/`VxAo` attributes only our shader knows about, so the face
+//! shading and the ambient occlusion it computes are baked into vertex colours instead.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+use serde_json::{json, Value};
+
+use super::{
+    chunk::VxChunkMesh, chunk_id, VxWorld, ATTRIBUTE_VX_AO, ATTRIBUTE_VX_TYPE, CHUNK_SIZE,
+};
+
+/// Where the texture atlas embedded in the exported files is read from
+pub const ATLAS_PATH: &str = "assets/textures.png";
+
+// The same values as `face_shading` and `ao_values` in chunk_fragment.wgsl
+const FACE_SHADING: [f32; 6] = [1.0, 0.5, 0.5, 0.8, 0.5, 0.8];
+const AO_VALUES: [f32; 4] = [0.1, 0.25, 0.5, 1.0];
+
+/// The meshes of several chunks merged together, in world coordinates
+#[derive(Debug, Clone, Default)]
+pub struct ExportMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Face shading and ambient occlusion, as a grey level
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    /// Meshes every chunk overlapping the box between two corners of the world
+    pub fn from_world(world: &VxWorld, corner_a: IVec3, corner_b: IVec3) -> Self {
+        let size = CHUNK_SIZE as i32;
+        let min = corner_a.min(corner_b).max(IVec3::ZERO) / size;
+        let max = corner_a.max(corner_b).max(IVec3::ZERO) / size;
+        let mut export = Self::default();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coord = (x as usize, y as usize, z as usize);
+                    if chunk_id(coord).is_some() {
+                        export.append_chunk(&VxChunkMesh::new(coord, &world.voxels));
+                    }
+                }
+            }
+        }
+        export
+    }
+
+    /// Adds the mesh of a chunk, moved to its place in the world
+    pub fn append_chunk(&mut self, chunk: &VxChunkMesh) {
+        let mesh = &chunk.mesh;
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Uint32(types)),
+            Some(VertexAttributeValues::Uint32(aos)),
+            Some(Indices::U32(indices)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(ATTRIBUTE_VX_TYPE),
+            mesh.attribute(ATTRIBUTE_VX_AO),
+            mesh.indices(),
+        )
+        else {
+            return;
+        };
+        let offset = (CHUNK_SIZE as f32)
+            * Vec3::new(
+                chunk.coord.0 as f32,
+                chunk.coord.1 as f32,
+                chunk.coord.2 as f32,
+            );
+        let first_index = self.positions.len() as u32;
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| (Vec3::from(*position) + offset).to_array()),
+        );
+        self.normals.extend_from_slice(normals);
+        self.uvs.extend_from_slice(uvs);
+        self.colors
+            .extend(types.iter().zip(aos).map(|(v_type, ao)| {
+                let shade = FACE_SHADING[*v_type as usize % 6] * AO_VALUES[*ao as usize % 4];
+                [shade, shade, shade, 1.0]
+            }));
+        self.indices
+            .extend(indices.iter().map(|index| first_index + index));
+    }
+
+    /// Writes a binary glTF file, the geometry and the PNG texture atlas living in its buffer
+    pub fn write_glb(&self, mut writer: impl Write, atlas_png: &[u8]) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut buffer_views = Vec::new();
+        let mut push_view = |bytes: &[u8], target: Option<u32>| {
+            while buffer.len() % 4 != 0 {
+                buffer.push(0);
+            }
+            let mut view =
+                json!({ "buffer": 0, "byteOffset": buffer.len(), "byteLength": bytes.len() });
+            if let Some(target) = target {
+                view["target"] = json!(target);
+            }
+            buffer.extend_from_slice(bytes);
+            buffer_views.push(view);
+            buffer_views.len() - 1
+        };
+        // Vertex attributes and indices, as in the `target` of the buffer views
+        const ARRAY_BUFFER: Option<u32> = Some(34962);
+        const ELEMENT_ARRAY_BUFFER: Option<u32> = Some(34963);
+
+        let mut json = json!({
+            "asset": { "version": "2.0", "generator": "bevy-voxel" },
+            "scene": 0,
+            "scenes": [{ "nodes": [] }],
+            "nodes": [],
+            "meshes": [],
+            "materials": [{
+                "name": "atlas",
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": 0 },
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.5,
+            }],
+            // Nearest filtering and clamping, to keep the pixel art crisp
+            "samplers": [{
+                "magFilter": 9728,
+                "minFilter": 9728,
+                "wrapS": 33071,
+                "wrapT": 33071,
+            }],
+            "textures": [{ "sampler": 0, "source": 0 }],
+        });
+
+        let mut accessors = Vec::new();
+        // glTF does not allow empty accessors, an empty region is exported as an empty scene
+        if !self.indices.is_empty() {
+            let (min, max) = self.positions.iter().fold(
+                (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+                |(min, max), position| (min.min((*position).into()), max.max((*position).into())),
+            );
+            let attributes: [(&[f32], &str, Option<Value>); 4] = [
+                (
+                    self.positions.as_flattened(),
+                    "VEC3",
+                    Some(json!([min.to_array(), max.to_array()])),
+                ),
+                (self.normals.as_flattened(), "VEC3", None),
+                (self.uvs.as_flattened(), "VEC2", None),
+                (self.colors.as_flattened(), "VEC4", None),
+            ];
+            for (values, kind, bounds) in attributes {
+                let bytes: Vec<u8> = values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect();
+                let mut accessor = json!({
+                    "bufferView": push_view(&bytes, ARRAY_BUFFER),
+                    "componentType": 5126,
+                    "count": self.positions.len(),
+                    "type": kind,
+                });
+                if let Some(bounds) = bounds {
+                    accessor["min"] = bounds[0].clone();
+                    accessor["max"] = bounds[1].clone();
+                }
+                accessors.push(accessor);
+            }
+            let bytes: Vec<u8> = self
+                .indices
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect();
+            accessors.push(json!({
+                "bufferView": push_view(&bytes, ELEMENT_ARRAY_BUFFER),
+                "componentType": 5125,
+                "count": self.indices.len(),
+                "type": "SCALAR",
+            }));
+
+            json["scenes"][0]["nodes"] = json!([0]);
+            json["nodes"] = json!([{ "name": "world", "mesh": 0 }]);
+            json["meshes"] = json!([{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2, "COLOR_0": 3 },
+                    "indices": 4,
+                    "material": 0,
+                }],
+            }]);
+        }
+        let image_view = push_view(atlas_png, None);
+        while buffer.len() % 4 != 0 {
+            buffer.push(0);
+        }
+        json["accessors"] = json!(accessors);
+        json["bufferViews"] = json!(buffer_views);
+        json["images"] = json!([{ "bufferView": image_view, "mimeType": "image/png" }]);
+        json["buffers"] = json!([{ "byteLength": buffer.len() }]);
+
+        let mut json_bytes = serde_json::to_vec(&json).map_err(io::Error::other)?;
+        while json_bytes.len() % 4 != 0 {
+            json_bytes.push(b' ');
+        }
+        // A 12 bytes header, then the JSON and the binary chunks
+        let length = 12 + 8 + json_bytes.len() + 8 + buffer.len();
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json_bytes)?;
+        writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&buffer)?;
+        writer.flush()
+    }
+
+    pub fn save_glb(&self, path: impl AsRef<Path>, atlas_png: &[u8]) -> io::Result<()> {
+        self.write_glb(BufWriter::new(fs::File::create(path)?), atlas_png)
+    }
+
+    /// Writes a Wavefront OBJ file, the colours being appended to the vertex positions as most
+    /// tools expect them. The material it uses is named `atlas`, and is found in
+    /// `material_library`.
+    pub fn write_obj(&self, mut writer: impl Write, material_library: &str) -> io::Result<()> {
+        writeln!(writer, "mtllib {material_library}")?;
+        writeln!(writer, "o world")?;
+        for ([x, y, z], [r, g, b, _]) in self.positions.iter().zip(&self.colors) {
+            writeln!(writer, "v {x} {y} {z} {r} {g} {b}")?;
+        }
+        // The texture coordinates of OBJ start from the bottom of the image
+        for [u, v] in &self.uvs {
+            writeln!(writer, "vt {u} {}", 1.0 - v)?;
+        }
+        for [x, y, z] in &self.normals {
+            writeln!(writer, "vn {x} {y} {z}")?;
+        }
+        writeln!(writer, "usemtl atlas")?;
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the OBJ file at `path`, along with its material library and the texture atlas
+    pub fn save_obj(&self, path: impl AsRef<Path>, atlas_png: &[u8]) -> io::Result<()> {
+        let path = path.as_ref();
+        let stem = path
+            .file_stem()
+            .map_or("world".into(), |stem| stem.to_string_lossy());
+        let material_library = format!("{stem}.mtl");
+        let texture = format!("{stem}_atlas.png");
+        fs::write(path.with_file_name(&texture), atlas_png)?;
+        fs::write(
+            path.with_file_name(&material_library),
+            format!("newmtl atlas\nKd 1 1 1\nmap_Kd {texture}\nmap_d {texture}\n"),
+        )?;
+        self.write_obj(BufWriter::new(fs::File::create(path)?), &material_library)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::{CubeTypes, Voxel},
+        CHUNK_VOLUME, WORLD_VOL,
+    };
+
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/two_blocks.obj");
+
+    // A block of stone with a block of sand on top of it, in the corner of the world
+    fn two_blocks() -> ExportMesh {
+        let mut my_world = VxWorld::from_voxels(vec![Voxel::default(); WORLD_VOL * CHUNK_VOLUME]);
+        my_world.set_voxel(IVec3::new(1, 1, 1), CubeTypes::Stone.into());
+        my_world.set_voxel(IVec3::new(1, 2, 1), CubeTypes::Sand.into());
+        ExportMesh::from_world(&my_world, IVec3::ZERO, IVec3::splat(3))
+    }
+
+    #[test]
+    fn obj_matches_the_snapshot() {
+        let mut obj = Vec::new();
+        two_blocks().write_obj(&mut obj, "two_blocks.mtl").unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let snapshot = fs::read_to_string(SNAPSHOT).unwrap();
+        assert_eq!(obj, snapshot);
+    }
+
+    // The length and the type of each chunk following the header
+    fn glb_chunks(glb: &[u8]) -> Vec<(usize, [u8; 4])> {
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(u32_at(4), 2);
+        assert_eq!(u32_at(8), glb.len());
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while offset < glb.len() {
+            let length = u32_at(offset);
+            assert_eq!(offset % 4, 0);
+            assert_eq!(length % 4, 0);
+            chunks.push((length, glb[offset + 4..offset + 8].try_into().unwrap()));
+            offset += 8 + length;
+        }
+        assert_eq!(offset, glb.len());
+        chunks
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned() {
+        // Neither the atlas nor the geometry are a multiple of 4 bytes long
+        let atlas = [1, 2, 3, 4, 5];
+        for export in [two_blocks(), ExportMesh::default()] {
+            let mut glb = Vec::new();
+            export.write_glb(&mut glb, &atlas).unwrap();
+            let chunks = glb_chunks(&glb);
+            assert_eq!(chunks.len(), 2);
+            assert_eq!(&chunks[0].1, b"JSON");
+            assert_eq!(&chunks[1].1, b"BIN\0");
+            let json: Value = serde_json::from_slice(&glb[20..20 + chunks[0].0]).unwrap();
+            assert_eq!(json["buffers"][0]["byteLength"], chunks[1].0);
+            for view in json["bufferViews"].as_array().unwrap() {
+                assert_eq!(view["byteOffset"].as_u64().unwrap() % 4, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! A small reader and writer for the NBT format, the binary format used by Minecraft and by the
+//! tools built around it to store structured data.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const TAG_END: u8 = 0;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Looks for a child of a compound tag
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(key),
+            _ => None,
+        }
+    }
+
+    /// Reads any integer tag as an `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(children) => Some(children),
+            _ => None,
+        }
+    }
+
+    /// The bytes of a byte array, as unsigned values
+    pub fn as_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Tag::ByteArray(values) => Some(values.iter().map(|value| *value as u8).collect()),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the root tag of an uncompressed NBT stream, along with its name
+pub fn read(reader: &mut impl Read) -> io::Result<(String, Tag)> {
+    let id = read_u8(reader)?;
+    if id == TAG_END {
+        return Err(invalid_data("the root tag can not be empty"));
+    }
+    let name = read_string(reader)?;
+    Ok((name, read_payload(reader, id, 0)?))
+}
+
+/// Writes a root tag as an uncompressed NBT stream
+pub fn write(writer: &mut impl Write, name: &str, tag: &Tag) -> io::Result<()> {
+    writer.write_all(&[tag.id()])?;
+    write_string(writer, name)?;
+    write_payload(writer, tag)
+}
+
+// Nested tags deeper than this are refused, as Minecraft does
+const MAX_DEPTH: usize = 512;
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_length(reader: &mut impl Read) -> io::Result<usize> {
+    let length = i32::from_be_bytes(read_array(reader)?);
+    usize::try_from(length).map_err(|_| invalid_data("negative length"))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    // Strings are stored as modified UTF-8, which only differs from UTF-8 for rare characters
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("tags are nested too deeply"));
+    }
+    Ok(match id {
+        1 => Tag::Byte(read_u8(reader)? as i8),
+        2 => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        3 => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+        4 => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        5 => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        6 => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        7 => {
+            let length = read_length(reader)?;
+            let mut bytes = vec![0; length];
+            reader.read_exact(&mut bytes)?;
+            Tag::ByteArray(bytes.into_iter().map(|byte| byte as i8).collect())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element_id = read_u8(reader)?;
+            let length = read_length(reader)?;
+            if element_id == TAG_END && length > 0 {
+                return Err(invalid_data("list of empty tags"));
+            }
+            let mut values = Vec::with_capacity(length.min(4096));
+            for _ in 0..length {
+                values.push(read_payload(reader, element_id, depth + 1)?);
+            }
+            Tag::List(values)
+        }
+        10 => {
+            let mut children = BTreeMap::new();
+            loop {
+                let child_id = read_u8(reader)?;
+                if child_id == TAG_END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                children.insert(name, read_payload(reader, child_id, depth + 1)?);
+            }
+            Tag::Compound(children)
+        }
+        11 => {
+            let length = read_length(reader)?;
+            let mut values = Vec::with_capacity(length.min(4096));
+            for _ in 0..length {
+                values.push(i32::from_be_bytes(read_array(reader)?));
+            }
+            Tag::IntArray(values)
+        }
+        12 => {
+            let length = read_length(reader)?;
+            let mut values = Vec::with_capacity(length.min(4096));
+            for _ in 0..length {
+                values.push(i64::from_be_bytes(read_array(reader)?));
+            }
+            Tag::LongArray(values)
+        }
+        _ => return Err(invalid_data("unknown tag type")),
+    })
+}
+
+fn write_length(writer: &mut impl Write, length: usize) -> io::Result<()> {
+    let length = i32::try_from(length).map_err(|_| invalid_data("too many elements"))?;
+    writer.write_all(&length.to_be_bytes())
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    let length = u16::try_from(value.len()).map_err(|_| invalid_data("string is too long"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn write_payload(writer: &mut impl Write, tag: &Tag) -> io::Result<()> {
+    match tag {
+        Tag::Byte(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Short(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Int(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Long(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Float(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Double(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            write_length(writer, values.len())?;
+            let bytes: Vec<u8> = values.iter().map(|value| *value as u8).collect();
+            writer.write_all(&bytes)
+        }
+        Tag::String(value) => write_string(writer, value),
+        Tag::List(values) => {
+            let element_id = values.first().map_or(TAG_END, Tag::id);
+            if values.iter().any(|value| value.id() != element_id) {
+                return Err(invalid_data("list elements must share the same type"));
+            }
+            writer.write_all(&[element_id])?;
+            write_length(writer, values.len())?;
+            for value in values {
+                write_payload(writer, value)?;
+            }
+            Ok(())
+        }
+        Tag::Compound(children) => {
+            for (name, child) in children {
+                writer.write_all(&[child.id()])?;
+                write_string(writer, name)?;
+                write_payload(writer, child)?;
+            }
+            writer.write_all(&[TAG_END])
+        }
+        Tag::IntArray(values) => {
+            write_length(writer, values.len())?;
+            for value in values {
+                writer.write_all(&value.to_be_bytes())?;
+            }
+            Ok(())
+        }
+        Tag::LongArray(values) => {
+            write_length(writer, values.len())?;
+            for value in values {
+                writer.write_all(&value.to_be_bytes())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(tag: &Tag) -> io::Result<(String, Tag)> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, "root", tag)?;
+        read(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn every_tag_survives_a_round_trip() {
+        let compound = |children: Vec<(&str, Tag)>| {
+            Tag::Compound(
+                children
+                    .into_iter()
+                    .map(|(name, tag)| (name.to_string(), tag))
+                    .collect(),
+            )
+        };
+        let tag = compound(vec![
+            ("byte", Tag::Byte(-3)),
+            ("short", Tag::Short(-30000)),
+            ("int", Tag::Int(i32::MIN)),
+            ("long", Tag::Long(i64::MAX)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-0.25)),
+            ("bytes", Tag::ByteArray(vec![0, -1, 127])),
+            (
+                "string",
+                Tag::String("minecraft:oak_log[axis=x]".to_string()),
+            ),
+            ("empty list", Tag::List(Vec::new())),
+            (
+                "list",
+                Tag::List(vec![
+                    compound(vec![("a", Tag::Int(1))]),
+                    compound(Vec::new()),
+                ]),
+            ),
+            ("ints", Tag::IntArray(vec![1, -2, 3])),
+            ("longs", Tag::LongArray(vec![i64::MIN, 0])),
+        ]);
+        assert_eq!(round_trip(&tag).unwrap(), ("root".to_string(), tag));
+    }
+
+    #[test]
+    fn mixed_lists_are_refused() {
+        assert!(round_trip(&Tag::List(vec![Tag::Int(1), Tag::Byte(1)])).is_err());
+    }
+
+    #[test]
+    fn truncated_streams_are_refused() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, "root", &Tag::LongArray(vec![1, 2, 3])).unwrap();
+        bytes.pop();
+        assert!(read(&mut bytes.as_slice()).is_err());
+        // An empty root
+        assert!(read(&mut [TAG_END].as_slice()).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        let mut tag = Tag::List(Vec::new());
+        for _ in 0..=MAX_DEPTH {
+            tag = Tag::List(vec![tag]);
+        }
+        assert!(round_trip(&tag).is_err());
+    }
+}
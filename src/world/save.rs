@@ -0,0 +1,246 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    ecs::entity::EntityHashMap,
+    prelude::*,
+    scene::{ron, serde::SceneDeserializer},
+};
+use serde::de::DeserializeSeed;
+
+use super::{
+    block_entity::BlockEntity, chunk_coord, chunk_id, BlockState, CubeTypes, Voxel, VxWorld,
+    VxWorldCoord, CHUNK_VOLUME, WORLD_VOL,
+};
+
+/// The folder the edited chunks are saved into
+pub const SAVE_DIR: &str = "saves/world";
+
+const CHUNK_EXTENSION: &str = "chunk";
+const BLOCK_ENTITIES_EXTENSION: &str = "scn.ron";
+
+fn chunk_path(chunk_id: usize, extension: &str) -> PathBuf {
+    let (x, y, z) = chunk_coord(chunk_id);
+    Path::new(SAVE_DIR).join(format!("{x}_{y}_{z}.{extension}"))
+}
+
+/// Packs the voxels of a chunk as runs of identical voxels, each one written as a little endian
+/// `u16` length followed by the cube type ID and the state bits.
+pub fn encode_chunk(voxels: &[Voxel]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut iter = voxels.iter().peekable();
+    while let Some(voxel) = iter.next() {
+        let mut length: u16 = 1;
+        while length < u16::MAX && iter.next_if_eq(&voxel).is_some() {
+            length += 1;
+        }
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.push(voxel.cube_type.id());
+        bytes.push(voxel.state.bits());
+    }
+    bytes
+}
+
+/// Unpacks voxels packed by [encode_chunk], returns None if the data is corrupted
+pub fn decode_chunk(bytes: &[u8]) -> Option<Vec<Voxel>> {
+    let mut voxels = Vec::with_capacity(CHUNK_VOLUME);
+    for run in bytes.chunks(4) {
+        let [l0, l1, id, state] = run else {
+            return None;
+        };
+        let voxel = Voxel::new(CubeTypes::from_id(*id)?, BlockState::from_bits(*state));
+        voxels.extend(std::iter::repeat_n(
+            voxel,
+            u16::from_le_bytes([*l0, *l1]) as usize,
+        ));
+        if voxels.len() > CHUNK_VOLUME {
+            return None;
+        }
+    }
+    (voxels.len() == CHUNK_VOLUME).then_some(voxels)
+}
+
+/// Replaces the generated voxels of the chunks that were saved
+pub fn load_chunks(voxels: &mut [Voxel]) {
+    let Ok(entries) = fs::read_dir(SAVE_DIR) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != CHUNK_EXTENSION)
+        {
+            continue;
+        }
+        let Some(chunk_id) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(parse_chunk_stem)
+        else {
+            continue;
+        };
+        match fs::read(&path).ok().and_then(|bytes| decode_chunk(&bytes)) {
+            Some(chunk) => voxels[chunk_id * CHUNK_VOLUME..(chunk_id + 1) * CHUNK_VOLUME]
+                .copy_from_slice(&chunk),
+            None => warn!("Could not load chunk {}", path.display()),
+        }
+    }
+}
+
+// Finds the chunk a file is about from its name, formatted as "x_y_z"
+fn parse_chunk_stem(stem: &str) -> Option<usize> {
+    let mut coords = stem.split('_').map(|coord| coord.parse::<usize>().ok());
+    chunk_id((coords.next()??, coords.next()??, coords.next()??))
+}
+
+/// Spawns the block entities saved along with their chunk
+pub fn load_block_entities(world: &mut World) {
+    let Ok(entries) = fs::read_dir(SAVE_DIR) else {
+        return;
+    };
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.to_string_lossy().ends_with(BLOCK_ENTITIES_EXTENSION) {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let scene = ron::Deserializer::from_str(&text)
+            .map_err(|error| error.to_string())
+            .and_then(|mut deserializer| {
+                SceneDeserializer {
+                    type_registry: &type_registry.read(),
+                }
+                .deserialize(&mut deserializer)
+                .map_err(|error| error.to_string())
+            });
+        match scene {
+            Ok(scene) => {
+                if let Err(error) = scene.write_to_world(world, &mut EntityHashMap::default()) {
+                    warn!(
+                        "Could not spawn block entities of {}: {error}",
+                        path.display()
+                    );
+                }
+            }
+            Err(error) => warn!("Could not load {}: {error}", path.display()),
+        }
+    }
+}
+
+/// Writes the edited chunks and the block entities to the disk when the game is closed
+pub fn save_world(world: &mut World) {
+    if world.resource::<Events<AppExit>>().is_empty() {
+        return;
+    }
+    if let Err(error) = fs::create_dir_all(SAVE_DIR) {
+        warn!("Could not create the save folder: {error}");
+        return;
+    }
+
+    // Block entities are grouped by the chunk they belong to
+    let mut chunk_entities: Vec<Vec<Entity>> = vec![Vec::new(); WORLD_VOL];
+    let mut q_block_entities = world.query::<(Entity, &BlockEntity)>();
+    for (entity, block_entity) in q_block_entities.iter(world) {
+        if let Some(world_coord) = VxWorldCoord::from_position(block_entity.position) {
+            chunk_entities[world_coord.chunk_id()].push(entity);
+        }
+    }
+
+    let Some(my_world) = world.get_resource::<VxWorld>() else {
+        return;
+    };
+    for &chunk_id in &my_world.edited_chunks {
+        if let Err(error) = fs::write(
+            chunk_path(chunk_id, CHUNK_EXTENSION),
+            encode_chunk(my_world.chunk_voxels(chunk_id)),
+        ) {
+            warn!("Could not save chunk {:?}: {error}", chunk_coord(chunk_id));
+        }
+    }
+
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+    for (chunk_id, entities) in chunk_entities.into_iter().enumerate() {
+        let path = chunk_path(chunk_id, BLOCK_ENTITIES_EXTENSION);
+        if entities.is_empty() {
+            // The last block entities of the chunk may have been destroyed since the last save
+            if path.exists() {
+                let _ = fs::remove_file(path);
+            }
+            continue;
+        }
+        let scene = DynamicSceneBuilder::from_world(world)
+            .deny_all_resources()
+            .extract_entities(entities.into_iter())
+            .build();
+        match scene.serialize(&type_registry) {
+            Ok(text) => {
+                if let Err(error) = fs::write(&path, text) {
+                    warn!("Could not save {}: {error}", path.display());
+                }
+            }
+            Err(error) => warn!("Could not serialize block entities: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block_state::Axis;
+
+    #[test]
+    fn chunks_round_trip() {
+        let log = Voxel::new(
+            CubeTypes::OakLog,
+            BlockState::default()
+                .with_axis(Axis::X)
+                .with_waterlogged(true),
+        );
+        let wheat = Voxel::new(CubeTypes::Wheat, BlockState::default().with_level(6));
+        let mut voxels = vec![Voxel::default(); CHUNK_VOLUME];
+        voxels[..1000].fill(CubeTypes::Stone.into());
+        voxels[1000] = log;
+        voxels[1001] = log;
+        voxels[5000] = wheat;
+        voxels[CHUNK_VOLUME - 1] = wheat;
+        let bytes = encode_chunk(&voxels);
+        // Stone, the two logs, air, wheat, air and the last wheat
+        assert_eq!(bytes.len(), 6 * 4);
+        assert_eq!(decode_chunk(&bytes), Some(voxels));
+    }
+
+    #[test]
+    fn long_runs_are_split() {
+        let voxels = vec![Voxel::from(CubeTypes::Dirt); u16::MAX as usize + 10];
+        let bytes = encode_chunk(&voxels);
+        let dirt = CubeTypes::Dirt.id();
+        assert_eq!(bytes, [0xff, 0xff, dirt, 0, 10, 0, dirt, 0]);
+        // More voxels than a chunk holds
+        assert_eq!(decode_chunk(&bytes), None);
+    }
+
+    #[test]
+    fn corrupted_chunks_are_refused() {
+        let voxels = vec![Voxel::from(CubeTypes::Sand); CHUNK_VOLUME];
+        let bytes = encode_chunk(&voxels);
+        assert!(decode_chunk(&bytes).is_some());
+        // Truncated
+        assert_eq!(decode_chunk(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(decode_chunk(&[]), None);
+        // Too few or too many voxels
+        assert_eq!(decode_chunk(&encode_chunk(&voxels[1..])), None);
+        let mut longer = bytes.clone();
+        longer.extend_from_slice(&[1, 0, 0, 0]);
+        assert_eq!(decode_chunk(&longer), None);
+        // Unknown cube type
+        let mut unknown = bytes;
+        unknown[2] = u8::MAX;
+        assert_eq!(decode_chunk(&unknown), None);
+    }
+}
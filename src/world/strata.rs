@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{
+    decoration::ChunkRng, CubeTypes, Voxel, VxWorldCoord, CHUNK_SIZE, CHUNK_VOLUME, WORLD_D,
+    WORLD_W,
+};
+
+/// Where an underground layer stops
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerBottom {
+    /// The layer is this many voxels thick, measured from the surface
+    Depth(i32),
+    /// The layer goes down to this height, wherever the surface is
+    Height(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct StrataLayer {
+    pub cube_type: CubeTypes,
+    pub bottom: LayerBottom,
+}
+
+/// How an ore is scattered underground
+#[derive(Debug, Clone)]
+pub struct OreVein {
+    pub cube_type: CubeTypes,
+    /// Lowest height a vein can reach
+    pub min_height: i32,
+    /// Height (excluded) under which the veins stay
+    pub max_height: i32,
+    /// Number of voxels of a vein
+    pub vein_size: u32,
+    /// Average number of veins for a column of chunks
+    pub veins_per_chunk: f32,
+}
+
+/// The description of everything below the surface: the layers, from the top to the bottom, and
+/// the ores hidden into them.
+#[derive(Debug, Clone, Resource)]
+pub struct UndergroundConfig {
+    pub layers: Vec<StrataLayer>,
+    pub ores: Vec<OreVein>,
+    /// The cube types ores are allowed to replace
+    pub ore_hosts: Vec<CubeTypes>,
+}
+
+impl Default for UndergroundConfig {
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                // Grass only grows on the surface, over a few layers of bare dirt
+                StrataLayer {
+                    cube_type: CubeTypes::Dirt,
+                    bottom: LayerBottom::Depth(1),
+                },
+                StrataLayer {
+                    cube_type: CubeTypes::BareDirt,
+                    bottom: LayerBottom::Depth(4),
+                },
+                StrataLayer {
+                    cube_type: CubeTypes::Stone,
+                    bottom: LayerBottom::Height(12),
+                },
+                StrataLayer {
+                    cube_type: CubeTypes::Deepslate,
+                    bottom: LayerBottom::Height(i32::MIN),
+                },
+            ],
+            ores: vec![
+                OreVein {
+                    cube_type: CubeTypes::CoalOre,
+                    min_height: 8,
+                    max_height: 64,
+                    vein_size: 12,
+                    veins_per_chunk: 6.0,
+                },
+                OreVein {
+                    cube_type: CubeTypes::IronOre,
+                    min_height: 0,
+                    max_height: 40,
+                    vein_size: 8,
+                    veins_per_chunk: 4.0,
+                },
+                OreVein {
+                    cube_type: CubeTypes::GoldOre,
+                    min_height: 0,
+                    max_height: 20,
+                    vein_size: 6,
+                    veins_per_chunk: 1.5,
+                },
+                OreVein {
+                    cube_type: CubeTypes::DiamondOre,
+                    min_height: 0,
+                    max_height: 12,
+                    vein_size: 4,
+                    veins_per_chunk: 0.5,
+                },
+            ],
+            ore_hosts: vec![CubeTypes::Stone, CubeTypes::Deepslate],
+        }
+    }
+}
+
+impl UndergroundConfig {
+    /// The cube type found at `height`, `depth` voxels below the surface of its column
+    pub fn layer_at(&self, height: i32, depth: i32) -> CubeTypes {
+        self.layers
+            .iter()
+            .find(|layer| match layer.bottom {
+                LayerBottom::Depth(thickness) => depth < thickness,
+                LayerBottom::Height(bottom) => height >= bottom,
+            })
+            .or(self.layers.last())
+            .map_or(CubeTypes::Empty, |layer| layer.cube_type)
+    }
+}
+
+/// Grows the ore veins inside of the layers. Each vein is a random walk starting from a random
+/// voxel of the chunk column, replacing the host voxels it goes through without leaving the
+/// heights of its ore. Ores without any height between their bounds are skipped.
+pub fn place_ores(voxels: &mut [Voxel], config: &UndergroundConfig, seed: u64) {
+    for (ore_index, ore) in config.ores.iter().enumerate() {
+        if ore.min_height >= ore.max_height {
+            warn!(
+                "Skipping {:?} veins, their heights {}..{} are empty",
+                ore.cube_type, ore.min_height, ore.max_height
+            );
+            continue;
+        }
+        for c_x in 0..WORLD_W {
+            for c_z in 0..WORLD_D {
+                // Every ore gets its own sequence, so tuning one leaves the others in place
+                let mut rng = ChunkRng::new(seed ^ ((ore_index as u64 + 1) << 32), c_x, c_z);
+                let mut veins = ore.veins_per_chunk.floor() as u32;
+                if rng.chance(ore.veins_per_chunk.fract()) {
+                    veins += 1;
+                }
+                for _ in 0..veins {
+                    let mut position = IVec3::new(
+                        (c_x * CHUNK_SIZE) as i32 + rng.range(0, CHUNK_SIZE as i32),
+                        rng.range(ore.min_height, ore.max_height),
+                        (c_z * CHUNK_SIZE) as i32 + rng.range(0, CHUNK_SIZE as i32),
+                    );
+                    for _ in 0..ore.vein_size {
+                        if let Some(world_coord) = VxWorldCoord::from_position(position) {
+                            let voxel = &mut voxels[world_coord.get_id()];
+                            if config.ore_hosts.contains(&voxel.cube_type) {
+                                *voxel = ore.cube_type.into();
+                            }
+                        }
+                        position += match rng.range(0, 6) {
+                            0 => IVec3::X,
+                            1 => IVec3::NEG_X,
+                            2 => IVec3::Y,
+                            3 => IVec3::NEG_Y,
+                            4 => IVec3::Z,
+                            _ => IVec3::NEG_Z,
+                        };
+                        position.y = position.y.clamp(ore.min_height, ore.max_height - 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Counts the voxels of each ore found in a chunk
+pub fn count_ores(chunk_voxels: &[Voxel], config: &UndergroundConfig) -> HashMap<CubeTypes, usize> {
+    let mut counts: HashMap<CubeTypes, usize> =
+        config.ores.iter().map(|ore| (ore.cube_type, 0)).collect();
+    for voxel in chunk_voxels {
+        if let Some(count) = counts.get_mut(&voxel.cube_type) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+/// Counts the ores of every chunk of a generated world, in the order the chunks are stored
+pub fn ore_counts_per_chunk(
+    voxels: &[Voxel],
+    config: &UndergroundConfig,
+) -> Vec<HashMap<CubeTypes, usize>> {
+    voxels
+        .chunks_exact(CHUNK_VOLUME)
+        .map(|chunk_voxels| count_ores(chunk_voxels, config))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{world::chunk_coord, CHUNK_AREA, WORLD_SEED, WORLD_VOL};
+
+    #[test]
+    fn layers_are_found_from_the_top() {
+        let config = UndergroundConfig::default();
+        let layer_at = |height, depth| config.layer_at(height, depth);
+        assert_eq!(layer_at(40, 0), CubeTypes::Dirt);
+        assert_eq!(layer_at(40, 1), CubeTypes::BareDirt);
+        assert_eq!(layer_at(40, 3), CubeTypes::BareDirt);
+        assert_eq!(layer_at(36, 4), CubeTypes::Stone);
+        assert_eq!(layer_at(12, 20), CubeTypes::Stone);
+        assert_eq!(layer_at(11, 21), CubeTypes::Deepslate);
+        assert_eq!(layer_at(-100, 200), CubeTypes::Deepslate);
+        // The surface layers win over the heights
+        assert_eq!(layer_at(5, 0), CubeTypes::Dirt);
+        let empty = UndergroundConfig {
+            layers: Vec::new(),
+            ..default()
+        };
+        assert_eq!(empty.layer_at(5, 0), CubeTypes::Empty);
+    }
+
+    fn position(index: usize) -> IVec3 {
+        let local = index % CHUNK_VOLUME;
+        VxWorldCoord::new(
+            chunk_coord(index / CHUNK_VOLUME),
+            (
+                local % CHUNK_SIZE,
+                local / CHUNK_AREA,
+                (local / CHUNK_SIZE) % CHUNK_SIZE,
+            ),
+        )
+        .position()
+    }
+
+    // The world before the ores: deepslate, stone, a band of dirt and a few shafts of air
+    fn rock(position: IVec3) -> CubeTypes {
+        match position {
+            _ if position.y >= 48 || position.x % 7 == 0 => CubeTypes::Empty,
+            _ if (20..24).contains(&position.y) => CubeTypes::Dirt,
+            _ if position.y < 12 => CubeTypes::Deepslate,
+            _ => CubeTypes::Stone,
+        }
+    }
+
+    fn with_ores(config: &UndergroundConfig, seed: u64) -> Vec<Voxel> {
+        let mut voxels: Vec<Voxel> = (0..WORLD_VOL * CHUNK_VOLUME)
+            .map(|index| rock(position(index)).into())
+            .collect();
+        place_ores(&mut voxels, config, seed);
+        voxels
+    }
+
+    #[test]
+    fn ores_are_placed_the_same_for_a_seed() {
+        let config = UndergroundConfig::default();
+        let counts = ore_counts_per_chunk(&with_ores(&config, 1), &config);
+        assert_eq!(
+            counts,
+            ore_counts_per_chunk(&with_ores(&config, 1), &config)
+        );
+        assert_ne!(
+            counts,
+            ore_counts_per_chunk(&with_ores(&config, 2), &config)
+        );
+        assert!(counts
+            .iter()
+            .flat_map(|counts| counts.values())
+            .any(|count| *count > 0));
+    }
+
+    #[test]
+    fn ores_stay_in_their_heights_and_hosts() {
+        let config = UndergroundConfig::default();
+        let voxels = with_ores(&config, WORLD_SEED);
+        for (index, voxel) in voxels.iter().enumerate() {
+            let position = position(index);
+            let Some(ore) = config
+                .ores
+                .iter()
+                .find(|ore| ore.cube_type == voxel.cube_type)
+            else {
+                assert_eq!(voxel.cube_type, rock(position), "at {position}");
+                continue;
+            };
+            assert!((ore.min_height..ore.max_height).contains(&position.y));
+            assert!(config.ore_hosts.contains(&rock(position)), "at {position}");
+        }
+    }
+
+    #[test]
+    fn ores_without_heights_are_skipped() {
+        let mut config = UndergroundConfig::default();
+        for ore in &mut config.ores {
+            ore.max_height = ore.min_height;
+        }
+        config.ores[0].max_height = config.ores[0].min_height - 10;
+        let counts = ore_counts_per_chunk(&with_ores(&config, WORLD_SEED), &config);
+        assert!(counts
+            .iter()
+            .flat_map(|counts| counts.values())
+            .all(|count| *count == 0));
+    }
+}
@@ -0,0 +1,340 @@
+//! The simulation of the blocks, run at a fixed rate of ticks. A voxel is ticked either when it was
+//! scheduled to be, a given number of ticks later, or at random, a few voxels of each chunk being
+//! picked at each tick. What a tick does depends on the cube type of the voxel, through the
+//! handlers registered for it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use super::{
+    block_state::MAX_LEVEL, chunk_coord, ChunkRng, CubeTypes, Voxel, VxWorld, CHUNK_SIZE,
+    WORLD_SEED, WORLD_VOL,
+};
+
+/// World ticks per second
+pub const TICK_RATE: f64 = 20.0;
+/// Voxels of each chunk ticked at random at each tick
+const RANDOM_TICKS_PER_CHUNK: u32 = 3;
+/// How far from a log leaves stay alive, in blocks along each axis
+const LEAF_RANGE: i32 = 4;
+/// Delays, in ticks, before the leaves next to a decayed one decay in turn
+const LEAF_DECAY_DELAY: (i32, i32) = (2, 10);
+/// Chance for a crop to grow when it is ticked
+const CROP_GROWTH_CHANCE: f32 = 0.3;
+/// How far from a heat source ice melts, in blocks along each axis
+const HEAT_RANGE: i32 = 2;
+
+/// What a block does when it is ticked, given its position
+pub type TickHandler = fn(&mut VxWorld, &mut BlockTicks, IVec3);
+
+/// The tick scheduler: the current tick, the ticks scheduled for later, and the handlers run on
+/// the ticked voxels
+#[derive(Debug, Resource)]
+pub struct BlockTicks {
+    tick: u64,
+    /// The positions to tick, by the tick they are due at
+    scheduled: BTreeMap<u64, Vec<IVec3>>,
+    // The positions waiting in `scheduled`, each one being scheduled once at a time
+    pending: HashSet<IVec3>,
+    pub random_ticks_per_chunk: u32,
+    rng: ChunkRng,
+    random_handlers: HashMap<CubeTypes, TickHandler>,
+    scheduled_handlers: HashMap<CubeTypes, TickHandler>,
+}
+
+impl Default for BlockTicks {
+    fn default() -> Self {
+        let mut ticks = Self {
+            tick: 0,
+            scheduled: BTreeMap::new(),
+            pending: HashSet::new(),
+            random_ticks_per_chunk: RANDOM_TICKS_PER_CHUNK,
+            rng: ChunkRng::new(WORLD_SEED, 0, 0),
+            random_handlers: HashMap::new(),
+            scheduled_handlers: HashMap::new(),
+        };
+        ticks.on_random_tick(CubeTypes::Dirt, spread_grass);
+        ticks.on_random_tick(CubeTypes::Wheat, grow_crop);
+        ticks.on_random_tick(CubeTypes::Leaves, decay_leaves);
+        ticks.on_scheduled_tick(CubeTypes::Leaves, decay_leaves);
+        ticks.on_random_tick(CubeTypes::Ice, melt_ice);
+        ticks
+    }
+}
+
+impl BlockTicks {
+    /// The number of ticks since the start
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Ticks the voxel at `position` in `delay` ticks, unless it is already waiting for a tick
+    pub fn schedule(&mut self, position: IVec3, delay: u64) {
+        if self.pending.insert(position) {
+            self.scheduled
+                .entry(self.tick + delay.max(1))
+                .or_default()
+                .push(position);
+        }
+    }
+
+    /// Runs `handler` on the voxels of a cube type picked by the random ticks
+    pub fn on_random_tick(&mut self, cube_type: CubeTypes, handler: TickHandler) {
+        self.random_handlers.insert(cube_type, handler);
+    }
+
+    /// Runs `handler` on the voxels of a cube type when their scheduled tick is due
+    pub fn on_scheduled_tick(&mut self, cube_type: CubeTypes, handler: TickHandler) {
+        self.scheduled_handlers.insert(cube_type, handler);
+    }
+
+    /// The random numbers of the handlers
+    pub fn rng(&mut self) -> &mut ChunkRng {
+        &mut self.rng
+    }
+}
+
+/// Runs a tick: the scheduled ticks due, then the random ticks of every chunk
+pub fn run_block_ticks(mut my_world: ResMut<VxWorld>, mut ticks: ResMut<BlockTicks>) {
+    ticks.tick += 1;
+    let next = ticks.tick + 1;
+    let later = ticks.scheduled.split_off(&next);
+    let due = std::mem::replace(&mut ticks.scheduled, later);
+    for position in due.into_values().flatten() {
+        ticks.pending.remove(&position);
+        let cube_type = my_world.get_voxel(position).cube_type;
+        if let Some(handler) = ticks.scheduled_handlers.get(&cube_type).copied() {
+            handler(&mut my_world, &mut ticks, position);
+        }
+    }
+
+    let size = CHUNK_SIZE as i32;
+    for chunk_id in 0..WORLD_VOL {
+        let (x, y, z) = chunk_coord(chunk_id);
+        let corner = IVec3::new(x as i32, y as i32, z as i32) * size;
+        for _ in 0..ticks.random_ticks_per_chunk {
+            let rng = ticks.rng();
+            let position =
+                corner + IVec3::new(rng.range(0, size), rng.range(0, size), rng.range(0, size));
+            let cube_type = my_world.get_voxel(position).cube_type;
+            if let Some(handler) = ticks.random_handlers.get(&cube_type).copied() {
+                handler(&mut my_world, &mut ticks, position);
+            }
+        }
+    }
+}
+
+// Whether a voxel is in the range of a cube type, along each axis
+fn is_near(my_world: &VxWorld, position: IVec3, range: i32, found: fn(CubeTypes) -> bool) -> bool {
+    (-range..=range).any(|x| {
+        (-range..=range).any(|y| {
+            (-range..=range)
+                .any(|z| found(my_world.get_voxel(position + IVec3::new(x, y, z)).cube_type))
+        })
+    })
+}
+
+// Grass dies under the blocks covering it, or spreads onto the uncovered dirt around
+fn spread_grass(my_world: &mut VxWorld, ticks: &mut BlockTicks, position: IVec3) {
+    let covered = |my_world: &VxWorld, position: IVec3| {
+        my_world
+            .get_voxel(position + IVec3::Y)
+            .cube_type
+            .is_opaque()
+    };
+    if covered(my_world, position) {
+        my_world.set_voxel(position, CubeTypes::BareDirt.into());
+        return;
+    }
+    let rng = ticks.rng();
+    let target = position + IVec3::new(rng.range(-1, 2), rng.range(-3, 2), rng.range(-1, 2));
+    if my_world.get_voxel(target).cube_type == CubeTypes::BareDirt && !covered(my_world, target) {
+        my_world.set_voxel(target, CubeTypes::Dirt.into());
+    }
+}
+
+// Crops grow a level at a time while they stand on dirt
+fn grow_crop(my_world: &mut VxWorld, ticks: &mut BlockTicks, position: IVec3) {
+    let voxel = my_world.get_voxel(position);
+    let ground = my_world.get_voxel(position - IVec3::Y).cube_type;
+    if voxel.state.level() >= MAX_LEVEL
+        || !matches!(ground, CubeTypes::Dirt | CubeTypes::BareDirt)
+        || !ticks.rng().chance(CROP_GROWTH_CHANCE)
+    {
+        return;
+    }
+    let state = voxel.state.with_level(voxel.state.level() + 1);
+    my_world.set_voxel(position, Voxel::new(voxel.cube_type, state));
+}
+
+// Leaves too far from any log disappear, along with the leaves next to them a few ticks later
+fn decay_leaves(my_world: &mut VxWorld, ticks: &mut BlockTicks, position: IVec3) {
+    let is_log = |cube_type: CubeTypes| {
+        matches!(
+            cube_type,
+            CubeTypes::OakLog | CubeTypes::BirchLog | CubeTypes::SpruceLog | CubeTypes::JungleLog
+        )
+    };
+    if is_near(my_world, position, LEAF_RANGE, is_log) {
+        return;
+    }
+    my_world.set_voxel(position, Voxel::default());
+    for offset in [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ] {
+        if my_world.get_voxel(position + offset).cube_type == CubeTypes::Leaves {
+            let delay = ticks.rng().range(LEAF_DECAY_DELAY.0, LEAF_DECAY_DELAY.1);
+            ticks.schedule(position + offset, delay as u64);
+        }
+    }
+}
+
+// Ice melts into water close to a heat source
+fn melt_ice(my_world: &mut VxWorld, _: &mut BlockTicks, position: IVec3) {
+    let is_hot = |cube_type: CubeTypes| matches!(cube_type, CubeTypes::Lava | CubeTypes::Furnace);
+    if is_near(my_world, position, HEAT_RANGE, is_hot) {
+        my_world.set_voxel(position, CubeTypes::Water.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CHUNK_VOLUME;
+
+    fn empty_world() -> VxWorld {
+        VxWorld::from_voxels(vec![Voxel::default(); WORLD_VOL * CHUNK_VOLUME])
+    }
+
+    // An app running a tick at each update, with scheduled ticks only
+    fn app() -> App {
+        let mut ticks = BlockTicks {
+            random_ticks_per_chunk: 0,
+            ..default()
+        };
+        // Each scheduled tick turns stone into cobblestone, then cobblestone into mossy one
+        ticks.on_scheduled_tick(CubeTypes::Stone, |my_world, _, position| {
+            my_world.set_voxel(position, CubeTypes::Cobblestone.into());
+        });
+        ticks.on_scheduled_tick(CubeTypes::Cobblestone, |my_world, _, position| {
+            my_world.set_voxel(position, CubeTypes::MossyCobblestone.into());
+        });
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(empty_world())
+            .insert_resource(ticks)
+            .add_systems(Update, run_block_ticks);
+        app
+    }
+
+    fn cube_type(app: &App, position: IVec3) -> CubeTypes {
+        app.world()
+            .resource::<VxWorld>()
+            .get_voxel(position)
+            .cube_type
+    }
+
+    #[test]
+    fn scheduled_ticks_fire_once_when_due() {
+        let mut app = app();
+        let position = IVec3::new(100, 40, 100);
+        app.world_mut()
+            .resource_mut::<VxWorld>()
+            .set_voxel(position, CubeTypes::Stone.into());
+        let mut ticks = app.world_mut().resource_mut::<BlockTicks>();
+        ticks.schedule(position, 3);
+        // Already waiting, so not ticked sooner nor twice
+        ticks.schedule(position, 1);
+        for _ in 0..2 {
+            app.update();
+            assert_eq!(cube_type(&app, position), CubeTypes::Stone);
+        }
+        app.update();
+        assert_eq!(cube_type(&app, position), CubeTypes::Cobblestone);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(cube_type(&app, position), CubeTypes::Cobblestone);
+        assert_eq!(app.world().resource::<BlockTicks>().tick(), 8);
+
+        // Once ticked, it can be scheduled again
+        app.world_mut()
+            .resource_mut::<BlockTicks>()
+            .schedule(position, 1);
+        app.update();
+        assert_eq!(cube_type(&app, position), CubeTypes::MossyCobblestone);
+    }
+
+    #[test]
+    fn grass_dies_covered_and_spreads_uncovered() {
+        let mut my_world = empty_world();
+        let mut ticks = BlockTicks::default();
+        let covered = IVec3::new(100, 40, 100);
+        my_world.set_voxel(covered, CubeTypes::Dirt.into());
+        my_world.set_voxel(covered + IVec3::Y, CubeTypes::Stone.into());
+        spread_grass(&mut my_world, &mut ticks, covered);
+        assert_eq!(my_world.get_voxel(covered).cube_type, CubeTypes::BareDirt);
+
+        let grass = IVec3::new(200, 40, 200);
+        my_world.set_voxel(grass, CubeTypes::Dirt.into());
+        my_world.set_voxel(grass + IVec3::X, CubeTypes::BareDirt.into());
+        for _ in 0..1000 {
+            spread_grass(&mut my_world, &mut ticks, grass);
+        }
+        assert_eq!(my_world.get_voxel(grass).cube_type, CubeTypes::Dirt);
+        assert_eq!(
+            my_world.get_voxel(grass + IVec3::X).cube_type,
+            CubeTypes::Dirt
+        );
+    }
+
+    #[test]
+    fn leaves_far_from_logs_decay() {
+        let mut my_world = empty_world();
+        let mut ticks = BlockTicks::default();
+        let log = IVec3::new(100, 40, 100);
+        my_world.set_voxel(log, CubeTypes::OakLog.into());
+        let near = log + IVec3::new(LEAF_RANGE, 0, 0);
+        let far = near + IVec3::X;
+        for position in [near, far, far + IVec3::X, far + IVec3::Y] {
+            my_world.set_voxel(position, CubeTypes::Leaves.into());
+        }
+        decay_leaves(&mut my_world, &mut ticks, near);
+        assert_eq!(my_world.get_voxel(near).cube_type, CubeTypes::Leaves);
+        assert!(ticks.pending.is_empty());
+
+        decay_leaves(&mut my_world, &mut ticks, far);
+        assert_eq!(my_world.get_voxel(far).cube_type, CubeTypes::Empty);
+        // The leaves around are scheduled to decay in turn, the ones in range of the log too
+        assert_eq!(
+            ticks.pending,
+            HashSet::from([near, far + IVec3::X, far + IVec3::Y])
+        );
+        assert!(ticks
+            .scheduled
+            .keys()
+            .all(|tick| { (LEAF_DECAY_DELAY.0 as u64..LEAF_DECAY_DELAY.1 as u64).contains(tick) }));
+    }
+
+    #[test]
+    fn ice_melts_near_lava() {
+        let mut my_world = empty_world();
+        let mut ticks = BlockTicks::default();
+        let lava = IVec3::new(100, 40, 100);
+        my_world.set_voxel(lava, CubeTypes::Lava.into());
+        let near = lava + IVec3::new(HEAT_RANGE, -HEAT_RANGE, HEAT_RANGE);
+        let far = lava + IVec3::new(0, 0, -HEAT_RANGE - 1);
+        for position in [near, far] {
+            my_world.set_voxel(position, CubeTypes::Ice.into());
+            melt_ice(&mut my_world, &mut ticks, position);
+        }
+        assert_eq!(my_world.get_voxel(near).cube_type, CubeTypes::Water);
+        assert_eq!(my_world.get_voxel(far).cube_type, CubeTypes::Ice);
+    }
+}
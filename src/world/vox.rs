@@ -0,0 +1,712 @@
+//! Import and export of MagicaVoxel models (`.vox`).
+//!
+//! A `.vox` file is a list of RIFF like chunks: the models (a `SIZE` chunk followed by the `XYZI`
+//! chunk listing their voxels), the palette (`RGBA`), and for the scenes made of several models,
+//! a scene graph of transform (`nTRN`), group (`nGRP`) and shape (`nSHP`) nodes placing them.
+//! MagicaVoxel has the Z axis pointing up, so its `(x, y, z)` is our `(x, z, -y)`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use super::{CubeTypes, VxWorld};
+
+/// The largest model MagicaVoxel can open, along each axis
+const MAX_MODEL_SIZE: i32 = 256;
+const VERSION: i32 = 150;
+
+/// A single model, its voxels being given in MagicaVoxel axes with their palette index
+#[derive(Debug, Clone)]
+pub struct VoxModel {
+    pub size: UVec3,
+    /// Position and palette index (from 1 to 255) of every non empty voxel
+    pub voxels: Vec<(UVec3, u8)>,
+}
+
+/// A model placed in the scene. The rotation maps the axes of the model onto the axes of the
+/// scene, and the translation gives the position of the center of the model.
+#[derive(Debug, Clone)]
+pub struct VoxInstance {
+    pub model: usize,
+    /// The rows of the rotation matrix, each one holding a single 1 or -1
+    pub rotation: [IVec3; 3],
+    pub translation: IVec3,
+}
+
+const IDENTITY: [IVec3; 3] = [IVec3::X, IVec3::Y, IVec3::Z];
+
+/// The content of a `.vox` file: the models, the way they are laid out, and the palette
+#[derive(Debug, Clone)]
+pub struct VoxScene {
+    pub models: Vec<VoxModel>,
+    pub instances: Vec<VoxInstance>,
+    /// The colours of the palette, the palette index `i` using `palette[i - 1]`
+    pub palette: [[u8; 4]; 256],
+}
+
+// The palette MagicaVoxel uses for the files without a palette: a cube of 215 colours, then
+// ramps of blue, green, red and grey, from the lightest to the darkest
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let cube = CUBE.into_iter().flat_map(|r| {
+        CUBE.into_iter()
+            .flat_map(move |g| CUBE.into_iter().map(move |b| [r, g, b, 0xff]))
+    });
+    let ramps = [[0, 0, 1], [0, 1, 0], [1, 0, 0], [1, 1, 1]]
+        .into_iter()
+        .flat_map(|[r, g, b]| {
+            RAMP.into_iter()
+                .map(move |value| [r * value, g * value, b * value, 0xff])
+        });
+    let mut palette = [[0; 4]; 256];
+    // The black of the cube is left out, the last colour being unused
+    for (color, default) in palette.iter_mut().zip(cube.take(215).chain(ramps)) {
+        *color = default;
+    }
+    palette
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Reads the little endian values of a chunk
+struct Parser<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if length > self.bytes.len() {
+            return Err(invalid_data("truncated chunk"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative length"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.length()?;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let mut dict = HashMap::new();
+        for _ in 0..self.length()? {
+            dict.insert(self.string()?, self.string()?);
+        }
+        Ok(dict)
+    }
+}
+
+enum Node {
+    Transform {
+        child: i32,
+        rotation: [IVec3; 3],
+        translation: IVec3,
+    },
+    Group(Vec<i32>),
+    Shape(Vec<usize>),
+}
+
+// The rotation is packed in a byte: the column of the non zero entry of the first two rows, then
+// the sign of each row
+fn decode_rotation(bits: u8) -> [IVec3; 3] {
+    let first = (bits & 3) as usize;
+    let second = ((bits >> 2) & 3) as usize;
+    let third = 3usize.saturating_sub(first + second);
+    let columns = [first, second, third].map(|column| column.min(2));
+    let mut rows = [IVec3::ZERO; 3];
+    for (index, row) in rows.iter_mut().enumerate() {
+        row[columns[index]] = if bits & (0x10 << index) != 0 { -1 } else { 1 };
+    }
+    rows
+}
+
+fn rotate(rotation: &[IVec3; 3], vector: IVec3) -> IVec3 {
+    IVec3::new(
+        rotation[0].dot(vector),
+        rotation[1].dot(vector),
+        rotation[2].dot(vector),
+    )
+}
+
+fn compose(parent: &[IVec3; 3], child: &[IVec3; 3]) -> [IVec3; 3] {
+    let columns = [
+        IVec3::new(child[0].x, child[1].x, child[2].x),
+        IVec3::new(child[0].y, child[1].y, child[2].y),
+        IVec3::new(child[0].z, child[1].z, child[2].z),
+    ];
+    parent.map(|row| {
+        IVec3::new(
+            row.dot(columns[0]),
+            row.dot(columns[1]),
+            row.dot(columns[2]),
+        )
+    })
+}
+
+impl VoxScene {
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut parser = Parser { bytes: &bytes };
+        if parser.take(4)? != b"VOX " {
+            return Err(invalid_data("not a MagicaVoxel file"));
+        }
+        parser.i32()?;
+
+        let mut models = Vec::new();
+        let mut size = None;
+        let mut palette = None;
+        let mut nodes = HashMap::new();
+        while !parser.bytes.is_empty() {
+            let id = parser.take(4)?;
+            let content_length = parser.length()?;
+            parser.length()?;
+            // Children follow the content of their parent, only the main chunk has some
+            let mut content = Parser {
+                bytes: parser.take(content_length)?,
+            };
+            match id {
+                b"SIZE" => {
+                    size = Some(UVec3::new(
+                        content.i32()? as u32,
+                        content.i32()? as u32,
+                        content.i32()? as u32,
+                    ));
+                }
+                b"XYZI" => {
+                    let size = size
+                        .take()
+                        .ok_or_else(|| invalid_data("model without size"))?;
+                    let mut voxels = Vec::new();
+                    for _ in 0..content.length()? {
+                        let voxel = content.take(4)?;
+                        voxels.push((
+                            UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32),
+                            voxel[3],
+                        ));
+                    }
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    let mut colors = [[0; 4]; 256];
+                    for color in colors.iter_mut() {
+                        color.copy_from_slice(content.take(4)?);
+                    }
+                    palette = Some(colors);
+                }
+                b"nTRN" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let child = content.i32()?;
+                    // Reserved ID and layer
+                    content.i32()?;
+                    content.i32()?;
+                    let frame = match content.length()? {
+                        0 => HashMap::new(),
+                        _ => content.dict()?,
+                    };
+                    let rotation = frame
+                        .get("_r")
+                        .and_then(|bits| bits.parse().ok())
+                        .map_or(IDENTITY, decode_rotation);
+                    let mut translation = IVec3::ZERO;
+                    if let Some(values) = frame.get("_t") {
+                        for (axis, value) in values.split_whitespace().take(3).enumerate() {
+                            translation[axis] = value.parse().unwrap_or(0);
+                        }
+                    }
+                    nodes.insert(
+                        node,
+                        Node::Transform {
+                            child,
+                            rotation,
+                            translation,
+                        },
+                    );
+                }
+                b"nGRP" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let mut children = Vec::new();
+                    for _ in 0..content.length()? {
+                        children.push(content.i32()?);
+                    }
+                    nodes.insert(node, Node::Group(children));
+                }
+                b"nSHP" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let mut shapes = Vec::new();
+                    for _ in 0..content.length()? {
+                        shapes.push(content.length()?);
+                        content.dict()?;
+                    }
+                    nodes.insert(node, Node::Shape(shapes));
+                }
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+        if nodes.is_empty() {
+            // Files without a scene graph lay every model at the origin
+            for (model, VoxModel { size, .. }) in models.iter().enumerate() {
+                instances.push(VoxInstance {
+                    model,
+                    rotation: IDENTITY,
+                    translation: (*size / 2).as_ivec3(),
+                });
+            }
+        } else {
+            collect_instances(&nodes, 0, IDENTITY, IVec3::ZERO, 0, &mut instances);
+        }
+        instances.retain(|instance| instance.model < models.len());
+        Ok(Self {
+            models,
+            instances,
+            palette: palette.unwrap_or_else(default_palette),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(fs::File::open(path)?)
+    }
+
+    /// Every voxel of the scene along with its palette index, in our axes and relative to the
+    /// lowest corner of the scene
+    pub fn voxels(&self) -> Vec<(IVec3, u8)> {
+        let mut voxels = Vec::new();
+        for instance in &self.instances {
+            let model = &self.models[instance.model];
+            let center = (model.size / 2).as_ivec3();
+            for (position, index) in &model.voxels {
+                let position =
+                    instance.translation + rotate(&instance.rotation, position.as_ivec3() - center);
+                voxels.push((IVec3::new(position.x, position.z, -position.y), *index));
+            }
+        }
+        let min = voxels
+            .iter()
+            .fold(IVec3::MAX, |min, (position, _)| min.min(*position));
+        for (position, _) in voxels.iter_mut() {
+            *position -= min;
+        }
+        voxels
+    }
+
+    /// Copies the non empty voxels of the box between two corners of the world, both included.
+    /// Boxes bigger than what MagicaVoxel can open are split into several models.
+    pub fn from_world(world: &VxWorld, corner_a: IVec3, corner_b: IVec3) -> Self {
+        let (min, max) = (corner_a.min(corner_b), corner_a.max(corner_b));
+        // Size of the box in MagicaVoxel axes
+        let size = IVec3::new(max.x - min.x + 1, max.z - min.z + 1, max.y - min.y + 1);
+
+        let mut palette = [[0; 4]; 256];
+        let mut indices = HashMap::new();
+        let mut models = Vec::new();
+        let mut instances = Vec::new();
+        for tile_x in (0..size.x).step_by(MAX_MODEL_SIZE as usize) {
+            for tile_y in (0..size.y).step_by(MAX_MODEL_SIZE as usize) {
+                for tile_z in (0..size.z).step_by(MAX_MODEL_SIZE as usize) {
+                    let origin = IVec3::new(tile_x, tile_y, tile_z);
+                    let model_size = (size - origin).min(IVec3::splat(MAX_MODEL_SIZE));
+                    let mut voxels = Vec::new();
+                    for x in 0..model_size.x {
+                        for y in 0..model_size.y {
+                            for z in 0..model_size.z {
+                                let vox = origin + IVec3::new(x, y, z);
+                                let cube_type = world
+                                    .get_voxel(IVec3::new(
+                                        min.x + vox.x,
+                                        min.y + vox.z,
+                                        max.z - vox.y,
+                                    ))
+                                    .cube_type;
+                                if cube_type == CubeTypes::Empty {
+                                    continue;
+                                }
+                                let next_index = indices.len() as u8 + 1;
+                                let index = *indices.entry(cube_type).or_insert_with(|| {
+                                    let [r, g, b] = cube_type.color();
+                                    palette[next_index as usize - 1] = [r, g, b, 255];
+                                    next_index
+                                });
+                                voxels.push((IVec3::new(x, y, z).as_uvec3(), index));
+                            }
+                        }
+                    }
+                    if voxels.is_empty() {
+                        continue;
+                    }
+                    instances.push(VoxInstance {
+                        model: models.len(),
+                        rotation: IDENTITY,
+                        translation: origin + model_size / 2,
+                    });
+                    models.push(VoxModel {
+                        size: model_size.as_uvec3(),
+                        voxels,
+                    });
+                }
+            }
+        }
+        Self {
+            models,
+            instances,
+            palette,
+        }
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut children = Vec::new();
+        for model in &self.models {
+            let mut size = Vec::new();
+            for value in model.size.to_array() {
+                size.extend_from_slice(&(value as i32).to_le_bytes());
+            }
+            write_chunk(&mut children, b"SIZE", &size);
+            let mut xyzi = (model.voxels.len() as i32).to_le_bytes().to_vec();
+            for (position, index) in &model.voxels {
+                xyzi.extend_from_slice(&[
+                    position.x as u8,
+                    position.y as u8,
+                    position.z as u8,
+                    *index,
+                ]);
+            }
+            write_chunk(&mut children, b"XYZI", &xyzi);
+        }
+
+        // A root transform holding a group, holding a transform and a shape for every instance
+        let mut group = Vec::new();
+        write_transform(&mut group, 0, 1, -1, &IDENTITY, IVec3::ZERO);
+        let mut content = Vec::new();
+        content.extend_from_slice(&1i32.to_le_bytes());
+        write_dict(&mut content, &[]);
+        content.extend_from_slice(&(self.instances.len() as i32).to_le_bytes());
+        for index in 0..self.instances.len() {
+            content.extend_from_slice(&(2 + 2 * index as i32).to_le_bytes());
+        }
+        write_chunk(&mut group, b"nGRP", &content);
+        for (index, instance) in self.instances.iter().enumerate() {
+            let node = 2 + 2 * index as i32;
+            write_transform(
+                &mut group,
+                node,
+                node + 1,
+                0,
+                &instance.rotation,
+                instance.translation,
+            );
+            let mut content = Vec::new();
+            content.extend_from_slice(&(node + 1).to_le_bytes());
+            write_dict(&mut content, &[]);
+            content.extend_from_slice(&1i32.to_le_bytes());
+            content.extend_from_slice(&(instance.model as i32).to_le_bytes());
+            write_dict(&mut content, &[]);
+            write_chunk(&mut group, b"nSHP", &content);
+        }
+        children.extend_from_slice(&group);
+        write_chunk(&mut children, b"RGBA", self.palette.as_flattened());
+
+        writer.write_all(b"VOX ")?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(b"MAIN")?;
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&(children.len() as i32).to_le_bytes())?;
+        writer.write_all(&children)?;
+        writer.flush()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(io::BufWriter::new(fs::File::create(path)?))
+    }
+}
+
+fn collect_instances(
+    nodes: &HashMap<i32, Node>,
+    node: i32,
+    rotation: [IVec3; 3],
+    translation: IVec3,
+    depth: usize,
+    instances: &mut Vec<VoxInstance>,
+) {
+    // Guards against scene graphs looping on themselves
+    if depth > 64 {
+        return;
+    }
+    match nodes.get(&node) {
+        Some(Node::Transform {
+            child,
+            rotation: local_rotation,
+            translation: local_translation,
+        }) => collect_instances(
+            nodes,
+            *child,
+            compose(&rotation, local_rotation),
+            translation + rotate(&rotation, *local_translation),
+            depth + 1,
+            instances,
+        ),
+        Some(Node::Group(children)) => {
+            for child in children {
+                collect_instances(nodes, *child, rotation, translation, depth + 1, instances);
+            }
+        }
+        Some(Node::Shape(models)) => {
+            for model in models {
+                instances.push(VoxInstance {
+                    model: *model,
+                    rotation,
+                    translation,
+                });
+            }
+        }
+        None => {}
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&0i32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+fn write_dict(bytes: &mut Vec<u8>, entries: &[(&str, String)]) {
+    bytes.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+    for (key, value) in entries {
+        for text in [key.as_bytes(), value.as_bytes()] {
+            bytes.extend_from_slice(&(text.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(text);
+        }
+    }
+}
+
+fn write_transform(
+    bytes: &mut Vec<u8>,
+    node: i32,
+    child: i32,
+    layer: i32,
+    rotation: &[IVec3; 3],
+    translation: IVec3,
+) {
+    let mut content = Vec::new();
+    content.extend_from_slice(&node.to_le_bytes());
+    write_dict(&mut content, &[]);
+    content.extend_from_slice(&child.to_le_bytes());
+    content.extend_from_slice(&(-1i32).to_le_bytes());
+    content.extend_from_slice(&layer.to_le_bytes());
+    content.extend_from_slice(&1i32.to_le_bytes());
+    write_dict(
+        &mut content,
+        &[
+            ("_r", encode_rotation(rotation).to_string()),
+            (
+                "_t",
+                format!("{} {} {}", translation.x, translation.y, translation.z),
+            ),
+        ],
+    );
+    write_chunk(bytes, b"nTRN", &content);
+}
+
+fn encode_rotation(rotation: &[IVec3; 3]) -> u8 {
+    let column = |row: IVec3| (0..3).find(|axis| row[*axis] != 0).unwrap_or(0) as u8;
+    let mut bits = column(rotation[0]) | (column(rotation[1]) << 2);
+    for (index, row) in rotation.iter().enumerate() {
+        if row.min_element() < 0 {
+            bits |= 0x10 << index;
+        }
+    }
+    bits
+}
+
+/// Picks the cube type used for each colour of a MagicaVoxel palette: the one whose texture is
+/// the closest in colour, unless the palette index is found in the override table.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct VoxPaletteMapping {
+    /// Cube types forced for some palette indices. Mapping an index to [CubeTypes::Empty] leaves
+    /// its voxels out.
+    pub overrides: HashMap<u8, CubeTypes>,
+}
+
+impl VoxPaletteMapping {
+    pub fn cube_type(&self, index: u8, color: [u8; 4]) -> CubeTypes {
+        if let Some(cube_type) = self.overrides.get(&index) {
+            return *cube_type;
+        }
+        let distance = |cube_type: &CubeTypes| {
+            cube_type
+                .color()
+                .iter()
+                .zip(color)
+                .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        // Fluids would flow away out of a model
+        CubeTypes::ALL[1..]
+            .iter()
+            .copied()
+            .filter(|cube_type| !cube_type.is_fluid())
+            .min_by_key(distance)
+            .unwrap_or_default()
+    }
+}
+
+impl VxWorld {
+    /// Writes the voxels of a MagicaVoxel scene with its lowest corner at `position`. The scene
+    /// can be far bigger than a chunk, its voxels being spread over every chunk it overlaps. Only
+    /// the non empty voxels of the scene are written, into the edit history so they can be undone.
+    /// Returns the number of voxels written.
+    pub fn stamp_vox(
+        &mut self,
+        scene: &VoxScene,
+        mapping: &VoxPaletteMapping,
+        position: IVec3,
+    ) -> usize {
+        let mut cube_types = HashMap::new();
+        let voxels: Vec<_> = scene
+            .voxels()
+            .into_iter()
+            .filter_map(|(offset, index)| {
+                let cube_type = *cube_types.entry(index).or_insert_with(|| {
+                    mapping.cube_type(index, scene.palette[(index as usize + 255) % 256])
+                });
+                (cube_type != CubeTypes::Empty).then(|| (position + offset, cube_type.into()))
+            })
+            .collect();
+        self.edit_voxels(voxels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two models, the second one turned a quarter around the vertical axis
+    fn scene() -> VoxScene {
+        let mut palette = [[0; 4]; 256];
+        palette[0] = [200, 10, 10, 255];
+        palette[1] = [10, 200, 10, 255];
+        VoxScene {
+            models: vec![
+                VoxModel {
+                    size: UVec3::new(2, 3, 4),
+                    voxels: vec![(UVec3::ZERO, 1), (UVec3::new(1, 2, 3), 2)],
+                },
+                VoxModel {
+                    size: UVec3::new(1, 2, 1),
+                    voxels: vec![(UVec3::new(0, 1, 0), 2)],
+                },
+            ],
+            instances: vec![
+                VoxInstance {
+                    model: 0,
+                    rotation: IDENTITY,
+                    translation: IVec3::new(1, 1, 2),
+                },
+                VoxInstance {
+                    model: 1,
+                    rotation: [IVec3::NEG_Y, IVec3::X, IVec3::Z],
+                    translation: IVec3::new(-5, 3, 0),
+                },
+            ],
+            palette,
+        }
+    }
+
+    #[test]
+    fn scenes_survive_a_round_trip() {
+        let scene = scene();
+        let mut bytes = Vec::new();
+        scene.write(&mut bytes).unwrap();
+        let read = VoxScene::read(bytes.as_slice()).unwrap();
+        assert_eq!(read.models.len(), 2);
+        for (read, model) in read.models.iter().zip(&scene.models) {
+            assert_eq!(read.size, model.size);
+            assert_eq!(read.voxels, model.voxels);
+        }
+        assert_eq!(read.instances.len(), 2);
+        for (read, instance) in read.instances.iter().zip(&scene.instances) {
+            assert_eq!(read.model, instance.model);
+            assert_eq!(read.rotation, instance.rotation);
+            assert_eq!(read.translation, instance.translation);
+        }
+        assert_eq!(read.palette, scene.palette);
+        assert_eq!(read.voxels(), scene.voxels());
+    }
+
+    #[test]
+    fn rotations_survive_their_encoding() {
+        // Every byte naming each axis once decodes to one of the 24 rotations or their mirror
+        // images, which survive being encoded again
+        let mut rotations = Vec::new();
+        for bits in 0..=u8::MAX {
+            let rotation = decode_rotation(bits);
+            let axes = rotation.map(|row| (0..3).position(|axis| row[axis] != 0));
+            if (0..3).all(|axis| axes.contains(&Some(axis))) {
+                assert_eq!(decode_rotation(encode_rotation(&rotation)), rotation);
+                if !rotations.contains(&rotation) {
+                    rotations.push(rotation);
+                }
+            }
+        }
+        assert_eq!(rotations.len(), 48);
+    }
+
+    #[test]
+    fn files_without_a_palette_use_the_default_one() {
+        let mut children = Vec::new();
+        write_chunk(
+            &mut children,
+            b"SIZE",
+            &[1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0],
+        );
+        write_chunk(
+            &mut children,
+            b"XYZI",
+            &[2, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 216],
+        );
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_chunk(&mut bytes, b"MAIN", &[]);
+        bytes.extend_from_slice(&children);
+        // The chunks of the main one follow its content
+        bytes[16..20].copy_from_slice(&(children.len() as i32).to_le_bytes());
+
+        let scene = VoxScene::read(bytes.as_slice()).unwrap();
+        let palette = scene.palette;
+        assert_eq!(palette[0], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(palette[1], [0xff, 0xff, 0xcc, 0xff]);
+        assert_eq!(palette[6], [0xff, 0xcc, 0xff, 0xff]);
+        assert_eq!(palette[214], [0x00, 0x00, 0x33, 0xff]);
+        assert_eq!(palette[215], [0x00, 0x00, 0xee, 0xff]);
+        assert_eq!(palette[225], [0x00, 0xee, 0x00, 0xff]);
+        assert_eq!(palette[235], [0xee, 0x00, 0x00, 0xff]);
+        assert_eq!(palette[254], [0x11, 0x11, 0x11, 0xff]);
+        assert_eq!(palette[255], [0, 0, 0, 0]);
+        // A model alone sits at the origin, upright
+        let mut voxels = scene.voxels();
+        voxels.sort_by_key(|(position, _)| position.y);
+        assert_eq!(voxels, [(IVec3::ZERO, 1), (IVec3::Y, 216)]);
+    }
+}